@@ -0,0 +1,203 @@
+use core::cmp::Ordering;
+use crate::platform::{BUFFER_WIDTH, BUFFER_HEIGHT, ColorCode, Color, plot};
+
+/// Something the game can draw onto, one character cell at a time.
+///
+/// Only `plot` has to be provided; `plot_str` and `plot_num` behave like their
+/// counterparts in `pluggable_interrupt_os::vga_buffer`.
+pub trait Canvas {
+    fn plot(&mut self, c: char, col: usize, row: usize, color: ColorCode);
+
+    /// Displays the string starting at the given coordinates, truncated at the right edge.
+    /// Returns the next column to use after the call.
+    fn plot_str(&mut self, s: &str, col: usize, row: usize, color: ColorCode) -> usize {
+        let end = BUFFER_WIDTH.min(col + s.len());
+        for (c, chr) in (col..end).zip(s.chars()) {
+            self.plot(chr, c, row, color);
+        }
+        end % BUFFER_WIDTH
+    }
+
+    /// Displays the number starting at the given coordinates, truncated at the right edge.
+    /// Returns the next column to use after the call.
+    fn plot_num(&mut self, num: isize, col: usize, row: usize, color: ColorCode) -> usize {
+        match num.cmp(&0) {
+            Ordering::Equal => {
+                self.plot('0', col, row, color);
+                (col + 1) % BUFFER_WIDTH
+            }
+            Ordering::Less => {
+                self.plot('-', col, row, color);
+                self.plot_num(-num, col + 1, row, color)
+            }
+            Ordering::Greater => {
+                let mut buffer = [' '; BUFFER_WIDTH];
+                let mut c = 0;
+                let mut num = num;
+                while num > 0 && c + col < buffer.len() {
+                    buffer[c] = ((num % 10 + '0' as isize) as u8) as char;
+                    num /= 10;
+                    c += 1;
+                }
+                for (i, digit) in buffer[..c].iter().enumerate() {
+                    self.plot(*digit, col + c - i - 1, row, color);
+                }
+                (col + c) % BUFFER_WIDTH
+            }
+        }
+    }
+
+    /// Fills every cell with a blank in the given color.
+    fn clear(&mut self, color: ColorCode) {
+        for row in 0..BUFFER_HEIGHT {
+            for col in 0..BUFFER_WIDTH {
+                self.plot(' ', col, row, color);
+            }
+        }
+    }
+}
+
+/// Draws straight into the VGA text buffer. This is what the kernel uses.
+pub struct VgaCanvas;
+
+impl Canvas for VgaCanvas {
+    fn plot(&mut self, c: char, col: usize, row: usize, color: ColorCode) {
+        plot(c, col, row, color);
+    }
+}
+
+/// An in-memory 80x25 grid of cells, so the game can be run and inspected without VGA hardware.
+#[derive(Copy, Clone)]
+pub struct GridCanvas {
+    cells: [[(char, ColorCode); BUFFER_WIDTH]; BUFFER_HEIGHT],
+}
+
+impl GridCanvas {
+    pub fn new() -> Self {
+        Self {cells: [[(' ', ColorCode::new(Color::Black, Color::Black)); BUFFER_WIDTH]; BUFFER_HEIGHT]}
+    }
+
+    /// Returns the character and color last plotted at the given coordinates.
+    pub fn peek(&self, col: usize, row: usize) -> (char, ColorCode) {
+        self.cells[row][col]
+    }
+
+    /// Returns how many cells currently show the given character.
+    pub fn count(&self, c: char) -> usize {
+        self.cells.iter().flatten().filter(|(chr, _)| *chr == c).count()
+    }
+
+    /// Returns true if the given row shows `s` starting at `col`.
+    pub fn shows(&self, s: &str, col: usize, row: usize) -> bool {
+        s.chars().enumerate().all(|(i, chr)| col + i < BUFFER_WIDTH && self.cells[row][col + i].0 == chr)
+    }
+}
+
+impl Canvas for GridCanvas {
+    fn plot(&mut self, c: char, col: usize, row: usize, color: ColorCode) {
+        self.cells[row][col] = (c, color);
+    }
+}
+
+/// Composes whole frames off-screen, then sends only the cells that changed since the previous
+/// flush on to another canvas. Drawing a full frame into it every tick costs one VGA write per
+/// changed cell rather than one per cell, and nothing is ever visibly blanked and redrawn.
+pub struct FrameBuffer {
+    back: GridCanvas,
+    front: GridCanvas,
+}
+
+impl FrameBuffer {
+    /// Both buffers start out matching a screen wiped by `clear_screen`.
+    pub fn new() -> Self {
+        Self {back: GridCanvas::new(), front: GridCanvas::new()}
+    }
+
+    /// Returns the frame being composed.
+    pub fn frame(&self) -> &GridCanvas {
+        &self.back
+    }
+
+    /// Writes every cell that differs from the last flushed frame to `target`.
+    /// Returns the number of cells written.
+    pub fn flush<C: Canvas>(&mut self, target: &mut C) -> usize {
+        let mut written = 0;
+        for row in 0..BUFFER_HEIGHT {
+            for col in 0..BUFFER_WIDTH {
+                let cell = self.back.cells[row][col];
+                if cell != self.front.cells[row][col] {
+                    target.plot(cell.0, col, row, cell.1);
+                    self.front.cells[row][col] = cell;
+                    written += 1;
+                }
+            }
+        }
+        written
+    }
+}
+
+impl Canvas for FrameBuffer {
+    fn plot(&mut self, c: char, col: usize, row: usize, color: ColorCode) {
+        self.back.plot(c, col, row, color);
+    }
+}
+
+/// Lets `write!` format text straight onto a canvas, one row, starting at a column.
+pub struct CanvasWriter<'a, C: Canvas> {
+    canvas: &'a mut C,
+    col: usize,
+    row: usize,
+    color: ColorCode,
+}
+
+impl<'a, C: Canvas> CanvasWriter<'a, C> {
+    pub fn new(canvas: &'a mut C, col: usize, row: usize, color: ColorCode) -> Self {
+        Self {canvas, col, row, color}
+    }
+
+    /// The column just past the last character written.
+    pub fn col(&self) -> usize {
+        self.col
+    }
+}
+
+impl<'a, C: Canvas> core::fmt::Write for CanvasWriter<'a, C> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for c in s.chars() {
+            if self.col < BUFFER_WIDTH {
+                self.canvas.plot(c, self.col, self.row, self.color);
+                self.col += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plots_numbers_and_strings() {
+        let mut canvas = GridCanvas::new();
+        let color = ColorCode::new(Color::White, Color::Black);
+        assert_eq!(canvas.plot_num(0, 0, 0, color), 1);
+        assert_eq!(canvas.plot_num(-305, 0, 1, color), 4);
+        assert_eq!(canvas.plot_str("Lives:", 10, 2, color), 16);
+        assert!(canvas.shows("0", 0, 0));
+        assert!(canvas.shows("-305", 0, 1));
+        assert!(canvas.shows("Lives:", 10, 2));
+        assert_eq!(canvas.peek(12, 2), ('v', color));
+        assert_eq!(canvas.count('-'), 1);
+    }
+
+    #[test]
+    fn truncates_at_the_right_edge() {
+        let mut canvas = GridCanvas::new();
+        let color = ColorCode::new(Color::White, Color::Black);
+        canvas.plot_num(123456, BUFFER_WIDTH - 3, 0, color);
+        assert!(canvas.shows("456", BUFFER_WIDTH - 3, 0));
+        canvas.plot_str("abcdef", BUFFER_WIDTH - 2, 1, color);
+        assert!(canvas.shows("ab", BUFFER_WIDTH - 2, 1));
+    }
+}
@@ -0,0 +1,359 @@
+use core::fmt::Write;
+use crate::platform::{BUFFER_HEIGHT, ColorCode, Color};
+use pc_keyboard::{DecodedKey, KeyCode};
+use crate::canvas::{Canvas, CanvasWriter};
+
+/// Everything a player can ask the game to do. Keys only matter through the
+/// `Bindings` table that turns them into one of these.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Fire,
+    Pause,
+    Restart,
+    Controls,
+    Edit,
+    Confirm,
+    Back,
+}
+
+impl Action {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::MoveUp => "Move up",
+            Action::MoveDown => "Move down",
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::Fire => "Fire",
+            Action::Pause => "Pause",
+            Action::Restart => "Restart",
+            Action::Controls => "Controls",
+            Action::Edit => "Level editor",
+            Action::Confirm => "Confirm",
+            Action::Back => "Back",
+        }
+    }
+}
+
+pub const ACTIONS: [Action; 11] = [Action::MoveUp, Action::MoveDown, Action::MoveLeft, Action::MoveRight,
+    Action::Fire, Action::Pause, Action::Restart, Action::Controls, Action::Edit, Action::Confirm, Action::Back];
+
+pub const MAX_BINDINGS: usize = 32;
+
+const SHARED: [(DecodedKey, Action); 8] = [
+    (DecodedKey::Unicode(' '), Action::Fire),
+    (DecodedKey::Unicode('p'), Action::Pause),
+    (DecodedKey::Unicode('r'), Action::Restart),
+    (DecodedKey::Unicode('c'), Action::Controls),
+    (DecodedKey::Unicode('e'), Action::Edit),
+    (DecodedKey::Unicode('\n'), Action::Confirm),
+    (DecodedKey::Unicode('\x1b'), Action::Back),
+    (DecodedKey::RawKey(KeyCode::Escape), Action::Back),
+];
+
+// '/' sits by the arrows, so the arrow player can fire while someone else has the space bar.
+const ARROWS: [(DecodedKey, Action); 5] = [
+    (DecodedKey::RawKey(KeyCode::ArrowUp), Action::MoveUp),
+    (DecodedKey::RawKey(KeyCode::ArrowDown), Action::MoveDown),
+    (DecodedKey::RawKey(KeyCode::ArrowLeft), Action::MoveLeft),
+    (DecodedKey::RawKey(KeyCode::ArrowRight), Action::MoveRight),
+    (DecodedKey::Unicode('/'), Action::Fire),
+];
+
+const WASD: [(DecodedKey, Action); 4] = [
+    (DecodedKey::Unicode('w'), Action::MoveUp),
+    (DecodedKey::Unicode('s'), Action::MoveDown),
+    (DecodedKey::Unicode('a'), Action::MoveLeft),
+    (DecodedKey::Unicode('d'), Action::MoveRight),
+];
+
+// Letters are bound in lower case, so shift or caps lock doesn't change what a key does.
+fn normalize(key: DecodedKey) -> DecodedKey {
+    match key {
+        DecodedKey::Unicode(c) => DecodedKey::Unicode(c.to_ascii_lowercase()),
+        key => key,
+    }
+}
+
+/// A table from keys to the actions they trigger. A key has at most one action;
+/// an action can have several keys.
+#[derive(Copy, Clone)]
+pub struct Bindings {
+    keys: [(DecodedKey, Action); MAX_BINDINGS],
+    count: usize,
+}
+
+impl Bindings {
+    pub fn empty() -> Self {
+        Self {keys: [(DecodedKey::Unicode(' '), Action::Fire); MAX_BINDINGS], count: 0}
+    }
+
+    /// Arrow keys and WASD both move.
+    pub fn standard() -> Self {
+        let mut bindings = Self::arrows();
+        bindings.add_all(&WASD);
+        bindings
+    }
+
+    pub fn arrows() -> Self {
+        let mut bindings = Self::empty();
+        bindings.add_all(&SHARED);
+        bindings.add_all(&ARROWS);
+        bindings
+    }
+
+    pub fn wasd() -> Self {
+        let mut bindings = Self::empty();
+        bindings.add_all(&WASD);
+        bindings.add_all(&SHARED);
+        bindings
+    }
+
+    /// Just movement on WASD and fire on the space bar, for the second player of a two-player game.
+    pub fn second_player() -> Self {
+        let mut bindings = Self::empty();
+        bindings.add_all(&WASD);
+        bindings.add(DecodedKey::Unicode(' '), Action::Fire);
+        bindings
+    }
+
+    fn add_all(&mut self, keys: &[(DecodedKey, Action)]) {
+        for (key, action) in keys {
+            self.add(*key, *action);
+        }
+    }
+
+    /// Makes `key` trigger `action`, taking it away from whatever it did before.
+    /// Returns false if the table is full.
+    pub fn add(&mut self, key: DecodedKey, action: Action) -> bool {
+        let key = normalize(key);
+        if let Some(i) = self.keys[..self.count].iter().position(|(k, _)| *k == key) {
+            self.keys[i].1 = action;
+            true
+        } else if self.count < MAX_BINDINGS {
+            self.keys[self.count] = (key, action);
+            self.count += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Makes `key` the only key for `action`. Refuses, returning false, if that would take the
+    /// last key away from Confirm or Back, since every menu needs them.
+    pub fn rebind(&mut self, action: Action, key: DecodedKey) -> bool {
+        let mut rebound = *self;
+        let mut kept = 0;
+        for i in 0..rebound.count {
+            if rebound.keys[i].1 != action {
+                rebound.keys[kept] = rebound.keys[i];
+                kept += 1;
+            }
+        }
+        rebound.count = kept;
+        rebound.add(key, action);
+        if rebound.key_for(Action::Confirm).is_none() || rebound.key_for(Action::Back).is_none() {
+            return false;
+        }
+        *self = rebound;
+        true
+    }
+
+    pub fn action(&self, key: DecodedKey) -> Option<Action> {
+        let key = normalize(key);
+        self.keys[..self.count].iter().find(|(k, _)| *k == key).map(|(_, a)| *a)
+    }
+
+    /// The first key bound to the action, if any.
+    pub fn key_for(&self, action: Action) -> Option<DecodedKey> {
+        self.keys[..self.count].iter().find(|(_, a)| *a == action).map(|(k, _)| *k)
+    }
+}
+
+/// Shows a key the way it is labelled on the keyboard. Returns the next column.
+pub fn plot_key<C: Canvas>(canvas: &mut C, key: DecodedKey, col: usize, row: usize, color: ColorCode) -> usize {
+    let mut out = CanvasWriter::new(canvas, col, row, color);
+    let _ = match key {
+        DecodedKey::Unicode(' ') => write!(out, "Space"),
+        DecodedKey::Unicode('\n') => write!(out, "Enter"),
+        DecodedKey::Unicode('\x1b') => write!(out, "Esc"),
+        DecodedKey::Unicode('\x08') => write!(out, "Backspace"),
+        DecodedKey::Unicode('\t') => write!(out, "Tab"),
+        DecodedKey::Unicode(c) if c.is_ascii_graphic() => write!(out, "{}", c.to_ascii_uppercase()),
+        DecodedKey::Unicode(c) => write!(out, "#{}", c as u32),
+        DecodedKey::RawKey(code) => write!(out, "{:?}", code),
+    };
+    out.col()
+}
+
+const PROFILES: [&str; 3] = ["Arrows + WASD", "Arrows only", "WASD only"];
+
+/// The rebinding screen: pick an action, press Confirm, then press the new key.
+/// The last rows switch the whole table to one of the built-in profiles.
+#[derive(Copy, Clone)]
+pub struct ControlsMenu {
+    selected: usize,
+    waiting: bool,
+    refused: bool,
+}
+
+impl ControlsMenu {
+    pub fn new() -> Self {
+        Self {selected: 0, waiting: false, refused: false}
+    }
+
+    /// Handles a key while the menu is open. Returns true when the menu should close.
+    pub fn key(&mut self, key: DecodedKey, bindings: &mut Bindings) -> bool {
+        let rows = ACTIONS.len() + PROFILES.len();
+        self.refused = false;
+        if self.waiting {
+            self.waiting = false;
+            if bindings.action(key) != Some(Action::Back) {
+                self.refused = !bindings.rebind(ACTIONS[self.selected], key);
+            }
+            return false;
+        }
+        let arrow = match key {
+            DecodedKey::RawKey(KeyCode::ArrowUp) => Some(Action::MoveUp),
+            DecodedKey::RawKey(KeyCode::ArrowDown) => Some(Action::MoveDown),
+            _ => None,
+        };
+        match arrow.or(bindings.action(key)) {
+            Some(Action::MoveUp) => self.selected = (self.selected + rows - 1) % rows,
+            Some(Action::MoveDown) => self.selected = (self.selected + 1) % rows,
+            Some(Action::Confirm) => {
+                if self.selected < ACTIONS.len() {
+                    self.waiting = true;
+                } else {
+                    *bindings = match self.selected - ACTIONS.len() {
+                        0 => Bindings::standard(),
+                        1 => Bindings::arrows(),
+                        _ => Bindings::wasd(),
+                    };
+                }
+            }
+            Some(Action::Back) | Some(Action::Controls) => return true,
+            _ => {}
+        }
+        false
+    }
+
+    pub fn draw<C: Canvas>(&self, canvas: &mut C, bindings: &Bindings) {
+        let text = ColorCode::new(Color::White, Color::Black);
+        let highlight = ColorCode::new(Color::Black, Color::LightGray);
+        canvas.clear(text);
+        canvas.plot_str("Controls", 36, 1, ColorCode::new(Color::Yellow, Color::Black));
+        let top = 3;
+        for (i, action) in ACTIONS.iter().enumerate() {
+            let color = if i == self.selected {highlight} else {text};
+            canvas.plot_str(action.name(), 20, top + i, color);
+            if i == self.selected && self.waiting {
+                canvas.plot_str("press a key...", 40, top + i, color);
+            } else if let Some(key) = bindings.key_for(*action) {
+                plot_key(canvas, key, 40, top + i, color);
+            } else {
+                canvas.plot_str("(unbound)", 40, top + i, color);
+            }
+        }
+        for (i, name) in PROFILES.iter().enumerate() {
+            let row = top + ACTIONS.len() + 1 + i;
+            let color = if ACTIONS.len() + i == self.selected {highlight} else {text};
+            let col = canvas.plot_str("Use profile: ", 20, row, color);
+            canvas.plot_str(name, col, row, color);
+        }
+        if self.refused {
+            canvas.plot_str("That key is the last one for Confirm or Back", 16, BUFFER_HEIGHT - 4,
+                ColorCode::new(Color::White, Color::Red));
+        }
+        canvas.plot_str("Up/Down to choose, Confirm to change, Back to return", 14, BUFFER_HEIGHT - 2, text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENTER: DecodedKey = DecodedKey::Unicode('\n');
+    const ESC: DecodedKey = DecodedKey::Unicode('\x1b');
+    const UP: DecodedKey = DecodedKey::RawKey(KeyCode::ArrowUp);
+    const DOWN: DecodedKey = DecodedKey::RawKey(KeyCode::ArrowDown);
+
+    #[test]
+    fn profiles_bind_what_they_say() {
+        let standard = Bindings::standard();
+        assert_eq!(standard.action(UP), Some(Action::MoveUp));
+        assert_eq!(standard.action(DecodedKey::Unicode('W')), Some(Action::MoveUp));
+        assert_eq!(standard.action(ENTER), Some(Action::Confirm));
+        assert_eq!(Bindings::arrows().action(DecodedKey::Unicode('w')), None);
+        assert_eq!(Bindings::wasd().action(UP), None);
+        assert_eq!(Bindings::wasd().action(ESC), Some(Action::Back));
+        let second = Bindings::second_player();
+        assert_eq!(second.action(DecodedKey::Unicode(' ')), Some(Action::Fire));
+        assert_eq!(second.action(DecodedKey::Unicode('p')), None);
+    }
+
+    #[test]
+    fn rebinding_replaces_every_key_for_the_action() {
+        let mut bindings = Bindings::standard();
+        assert!(bindings.rebind(Action::MoveUp, DecodedKey::Unicode('i')));
+        assert_eq!(bindings.action(DecodedKey::Unicode('i')), Some(Action::MoveUp));
+        assert_eq!(bindings.action(UP), None);
+        assert_eq!(bindings.action(DecodedKey::Unicode('w')), None);
+        // A key can only do one thing, so taking it moves it.
+        assert!(bindings.rebind(Action::Fire, DecodedKey::Unicode('p')));
+        assert_eq!(bindings.action(DecodedKey::Unicode('p')), Some(Action::Fire));
+        assert_eq!(bindings.key_for(Action::Pause), None);
+    }
+
+    #[test]
+    fn confirm_and_back_keep_a_key() {
+        let mut bindings = Bindings::standard();
+        assert!(!bindings.rebind(Action::MoveUp, ENTER));
+        assert_eq!(bindings.action(ENTER), Some(Action::Confirm));
+        assert_eq!(bindings.action(UP), Some(Action::MoveUp));
+        // Esc is one of two keys for Back, so it can go, but not the last one as well.
+        assert!(bindings.rebind(Action::Fire, ESC));
+        assert!(!bindings.rebind(Action::Fire, DecodedKey::RawKey(KeyCode::Escape)));
+        assert!(bindings.rebind(Action::Confirm, DecodedKey::Unicode('y')));
+        assert_eq!(bindings.action(ENTER), None);
+    }
+
+    #[test]
+    fn menu_rebinds_and_switches_profiles() {
+        let mut bindings = Bindings::standard();
+        let mut menu = ControlsMenu::new();
+        for key in [DOWN, DOWN, DOWN, DOWN, ENTER, DecodedKey::Unicode('f')] {
+            assert!(!menu.key(key, &mut bindings));
+        }
+        assert_eq!(bindings.action(DecodedKey::Unicode('f')), Some(Action::Fire));
+        // Esc while waiting for a key leaves the binding alone.
+        menu.key(ENTER, &mut bindings);
+        menu.key(ESC, &mut bindings);
+        assert_eq!(bindings.key_for(Action::Fire), Some(DecodedKey::Unicode('f')));
+        // Up from the first row wraps round to the last profile.
+        let mut menu = ControlsMenu::new();
+        menu.key(UP, &mut bindings);
+        menu.key(ENTER, &mut bindings);
+        assert_eq!(bindings.action(UP), None);
+        assert_eq!(bindings.action(DecodedKey::Unicode(' ')), Some(Action::Fire));
+        assert!(menu.key(ESC, &mut bindings));
+    }
+
+    #[test]
+    fn menu_cannot_lock_itself_out() {
+        let mut bindings = Bindings::standard();
+        let mut menu = ControlsMenu::new();
+        menu.key(ENTER, &mut bindings);
+        menu.key(ENTER, &mut bindings);
+        assert!(menu.refused);
+        assert_eq!(bindings.action(ENTER), Some(Action::Confirm));
+        // Enter still works, so the menu can still change things.
+        menu.key(ENTER, &mut bindings);
+        menu.key(DecodedKey::Unicode('i'), &mut bindings);
+        assert!(!menu.refused);
+        assert_eq!(bindings.action(DecodedKey::Unicode('i')), Some(Action::MoveUp));
+    }
+}
@@ -0,0 +1,171 @@
+use crate::platform::{BUFFER_WIDTH, BUFFER_HEIGHT, ColorCode, Color};
+use crate::platform::serial_print;
+use pc_keyboard::{DecodedKey, KeyCode};
+use crate::canvas::Canvas;
+use crate::level::{Level, LevelError};
+use crate::{Tile, BRITTLE_HITS};
+
+const HELP: &str = "EDIT #:wall k:tile 1-9:tele s:spawn t:pickup p:start Ent:play x:export Esc:quit";
+
+// What 'k' steps a cell through.
+const SPECIALS: [Tile; 8] = [Tile::Floor, Tile::Brittle(BRITTLE_HITS), Tile::Gate(0), Tile::Gate(1), Tile::Gate(2),
+    Tile::Gate(3), Tile::Ice, Tile::Slow];
+
+/// What the game should do after the editor has handled a key.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum EditorAction {
+    Stay,
+    PlayTest,
+    Quit,
+}
+
+// Edits a copy of a level in place: walls, spawn points, power-up tiles and the player start.
+#[derive(Copy, Clone)]
+pub struct Editor {
+    level: Level,
+    x: usize,
+    y: usize,
+    // Why the last play-test or export was refused, until the next key.
+    error: Option<LevelError>,
+}
+
+impl Editor {
+    pub fn new(level: Level) -> Self {
+        Self {level, x: BUFFER_WIDTH / 2, y: BUFFER_HEIGHT / 2, error: None}
+    }
+
+    pub fn level(&self) -> &Level {
+        &self.level
+    }
+
+    pub fn key(&mut self, key: DecodedKey) -> EditorAction {
+        self.error = None;
+        match key {
+            DecodedKey::RawKey(KeyCode::ArrowUp) => self.y = self.y.saturating_sub(1),
+            DecodedKey::RawKey(KeyCode::ArrowDown) => self.y = (self.y + 1).min(BUFFER_HEIGHT - 1),
+            DecodedKey::RawKey(KeyCode::ArrowLeft) => self.x = self.x.saturating_sub(1),
+            DecodedKey::RawKey(KeyCode::ArrowRight) => self.x = (self.x + 1).min(BUFFER_WIDTH - 1),
+            // The border stays solid, so nothing can leave the arena.
+            DecodedKey::Unicode('#' | 'w' | 'k' | '1'..='9') if self.on_border() => {}
+            DecodedKey::Unicode('#') | DecodedKey::Unicode('w') => self.level.walls.toggle(self.y, self.x),
+            DecodedKey::Unicode('k') => {
+                let tile = self.level.walls.tile(self.y, self.x);
+                let next = SPECIALS.iter().position(|t| *t == tile).map_or(1, |i| (i + 1) % SPECIALS.len());
+                self.level.walls.set(self.y, self.x, SPECIALS[next]);
+            }
+            DecodedKey::Unicode(c @ '1'..='9') => self.level.walls.set(self.y, self.x, Tile::Teleporter(c as u8 - b'0')),
+            DecodedKey::Unicode('s') => {
+                self.level.toggle_spawn(self.x, self.y);
+            }
+            DecodedKey::Unicode('t') => {
+                self.level.cycle_pickup(self.x, self.y);
+            }
+            DecodedKey::Unicode('p') => self.level.start = (self.x, self.y),
+            DecodedKey::Unicode('x') => {
+                if let Err(e) = self.export() {
+                    self.error = Some(e);
+                }
+            }
+            DecodedKey::Unicode('\n') | DecodedKey::RawKey(KeyCode::Enter) => match self.level.validate() {
+                Ok(()) => return EditorAction::PlayTest,
+                Err(e) => self.error = Some(e),
+            },
+            DecodedKey::Unicode('\x1b') | DecodedKey::RawKey(KeyCode::Escape) => return EditorAction::Quit,
+            _ => {}
+        }
+        EditorAction::Stay
+    }
+
+    /// Sends the level over COM1 in the text format the built-in levels are written in,
+    /// so it can be pasted into a file under `levels/`. A level that wouldn't load back
+    /// isn't sent.
+    pub fn export(&self) -> Result<(), LevelError> {
+        self.level.validate()?;
+        serial_print!("{}", self.level);
+        Ok(())
+    }
+
+    fn on_border(&self) -> bool {
+        self.x == 0 || self.y == 0 || self.x == BUFFER_WIDTH - 1 || self.y == BUFFER_HEIGHT - 1
+    }
+
+    pub fn draw<C: Canvas>(&self, canvas: &mut C) {
+        self.level.walls.draw(canvas);
+        for (x, y) in self.level.spawns() {
+            canvas.plot('S', *x, *y, ColorCode::new(Color::DarkGray, Color::Black));
+        }
+        for (x, y, kind) in self.level.pickups() {
+            canvas.plot(kind.glyph(), *x, *y, kind.color());
+        }
+        canvas.plot('*', self.level.start.0, self.level.start.1, ColorCode::new(Color::Green, Color::Black));
+
+        let (c, color) = self.cell(self.x, self.y);
+        canvas.plot(c, self.x, self.y, ColorCode::new(color.background(), Color::LightGray));
+
+        canvas.plot_str(HELP, 1, 0, ColorCode::new(Color::White, Color::Blue));
+        let col = canvas.plot_num(self.x as isize, 1, BUFFER_HEIGHT - 1, ColorCode::new(Color::White, Color::Blue));
+        let col = canvas.plot_str(",", col, BUFFER_HEIGHT - 1, ColorCode::new(Color::White, Color::Blue));
+        let col = canvas.plot_num(self.y as isize, col, BUFFER_HEIGHT - 1, ColorCode::new(Color::White, Color::Blue));
+        if let Some(e) = self.error {
+            let col = canvas.plot_str(" Can't use this level: ", col, BUFFER_HEIGHT - 1, ColorCode::new(Color::White, Color::Red));
+            canvas.plot_str(e.message(), col, BUFFER_HEIGHT - 1, ColorCode::new(Color::White, Color::Red));
+        }
+    }
+
+    // What is shown at a cell when the cursor is not on it.
+    fn cell(&self, x: usize, y: usize) -> (char, ColorCode) {
+        if self.level.start == (x, y) {
+            ('*', ColorCode::new(Color::Green, Color::Black))
+        } else if let Some(kind) = self.level.pickup_at(x, y) {
+            (kind.glyph(), kind.color())
+        } else if self.level.is_spawn(x, y) {
+            ('S', ColorCode::new(Color::DarkGray, Color::Black))
+        } else {
+            let tile = self.level.walls.tile(y, x);
+            (tile.glyph(), tile.color())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::LEVELS;
+
+    fn press(editor: &mut Editor, keys: &str) -> EditorAction {
+        keys.chars().map(|c| editor.key(DecodedKey::Unicode(c))).last().unwrap_or(EditorAction::Stay)
+    }
+
+    fn go_to(editor: &mut Editor, x: usize, y: usize) {
+        editor.x = x;
+        editor.y = y;
+    }
+
+    #[test]
+    fn refuses_levels_that_would_not_load() {
+        let mut editor = Editor::new(Level::parse(LEVELS[0]).unwrap());
+        go_to(&mut editor, 10, 10);
+        assert_eq!(press(&mut editor, "3\n"), EditorAction::Stay);
+        assert_eq!(editor.error, Some(LevelError::UnpairedTeleporter('3')));
+        assert_eq!(editor.export(), Err(LevelError::UnpairedTeleporter('3')));
+        go_to(&mut editor, 20, 10);
+        assert_eq!(press(&mut editor, "3\n"), EditorAction::PlayTest);
+        assert_eq!(editor.error, None);
+        // The start is under the cursor, so walling it in is caught too.
+        go_to(&mut editor, 30, 10);
+        press(&mut editor, "p#");
+        assert_eq!(press(&mut editor, "\n"), EditorAction::Stay);
+        assert_eq!(editor.error, Some(LevelError::StartInWall));
+    }
+
+    #[test]
+    fn keeps_the_border_solid() {
+        let mut editor = Editor::new(Level::parse(LEVELS[0]).unwrap());
+        for (x, y) in [(0, 5), (BUFFER_WIDTH - 1, 5), (5, 0), (5, BUFFER_HEIGHT - 1)] {
+            go_to(&mut editor, x, y);
+            press(&mut editor, "#k5");
+            assert_eq!(editor.level().walls.tile(y, x), Tile::Wall);
+        }
+        assert!(editor.level().validate().is_ok());
+    }
+}
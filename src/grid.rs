@@ -0,0 +1,252 @@
+use crate::platform::{BUFFER_WIDTH, BUFFER_HEIGHT};
+
+const NO_SHOOTER: u8 = u8::MAX;
+const NO_PICKUP: u8 = 0;
+// Pickup codes: junk slots count up from 1, power-up slots from POWER_UP_CODE.
+const POWER_UP_CODE: u8 = 0x80;
+
+/// Something a player collects by moving onto it, by its slot in the game.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Pickup {
+    Junk(usize),
+    PowerUp(usize),
+}
+
+impl Pickup {
+    fn code(&self) -> u8 {
+        match self {
+            Pickup::Junk(i) => 1 + *i as u8,
+            Pickup::PowerUp(i) => POWER_UP_CODE + *i as u8,
+        }
+    }
+
+    fn from_code(code: u8) -> Option<Self> {
+        match code {
+            NO_PICKUP => None,
+            code if code >= POWER_UP_CODE => Some(Pickup::PowerUp((code - POWER_UP_CODE) as usize)),
+            code => Some(Pickup::Junk(code as usize - 1)),
+        }
+    }
+}
+
+// Each layer of a cell is kept apart, since a projectile can pass over a pickup or a shooter.
+// Shooters and projectiles that share a cell are chained through their slots: the cell holds
+// the last one added and how many there are, and each slot names the one added before it.
+#[derive(Copy, Clone)]
+struct Cell {
+    wall: bool,
+    shooters: u8,
+    // The registry slot of the last shooter added, if `shooters` isn't 0.
+    shooter: u8,
+    projectiles: u16,
+    // The pool slot of the last projectile added, if `projectiles` isn't 0.
+    projectile: u16,
+    pickup: u8,
+}
+
+const EMPTY_CELL: Cell = Cell {wall: false, shooters: 0, shooter: NO_SHOOTER, projectiles: 0, projectile: 0, pickup: NO_PICKUP};
+
+/// What is in each cell of the arena, so a collision is a lookup rather than a search.
+/// The game rebuilds it once things have moved, and keeps it up to date as things are
+/// destroyed or collected in between. The wall layer is only set when a level starts.
+/// Anything off the map reads as empty, except that it reads as wall.
+/// `P` and `S` are the sizes of the projectile pool and the shooter registry.
+#[derive(Copy, Clone)]
+pub struct Occupancy<const P: usize, const S: usize> {
+    cells: [[Cell; BUFFER_WIDTH]; BUFFER_HEIGHT],
+    // For each slot in a cell's chain, the slot added to that cell before it.
+    next_projectile: [u16; P],
+    next_shooter: [u8; S],
+}
+
+impl<const P: usize, const S: usize> Occupancy<P, S> {
+    pub fn new() -> Self {
+        Self {cells: [[EMPTY_CELL; BUFFER_WIDTH]; BUFFER_HEIGHT], next_projectile: [0; P], next_shooter: [0; S]}
+    }
+
+    /// Empties every cell of all but its wall.
+    pub fn clear(&mut self) {
+        for cell in self.cells.iter_mut().flatten() {
+            *cell = Cell {wall: cell.wall, ..EMPTY_CELL};
+        }
+    }
+
+    /// Takes down every wall, ready for a new level's.
+    pub fn clear_walls(&mut self) {
+        for cell in self.cells.iter_mut().flatten() {
+            cell.wall = false;
+        }
+    }
+
+    fn cell(&self, x: usize, y: usize) -> Option<&Cell> {
+        self.cells.get(y).and_then(|row| row.get(x))
+    }
+
+    fn cell_mut(&mut self, x: usize, y: usize) -> Option<&mut Cell> {
+        self.cells.get_mut(y).and_then(|row| row.get_mut(x))
+    }
+
+    pub fn add_wall(&mut self, x: usize, y: usize) {
+        if let Some(cell) = self.cell_mut(x, y) {
+            cell.wall = true;
+        }
+    }
+
+    pub fn add_shooter(&mut self, x: usize, y: usize, slot: usize) {
+        let Some(cell) = self.cells.get_mut(y).and_then(|row| row.get_mut(x)) else {
+            return;
+        };
+        self.next_shooter[slot] = cell.shooter;
+        cell.shooter = slot as u8;
+        cell.shooters += 1;
+    }
+
+    /// Takes the shooter in `slot` out of the cell, if it is there.
+    pub fn remove_shooter(&mut self, x: usize, y: usize, slot: usize) {
+        let Some(cell) = self.cells.get_mut(y).and_then(|row| row.get_mut(x)) else {
+            return;
+        };
+        // Only the shooters sharing this cell are walked, never the whole registry.
+        let (mut prev, mut at) = (None, cell.shooter as usize);
+        for _ in 0..cell.shooters {
+            if at == slot {
+                match prev {
+                    Some(prev) => self.next_shooter[prev] = self.next_shooter[slot],
+                    None => cell.shooter = self.next_shooter[slot],
+                }
+                cell.shooters -= 1;
+                if cell.shooters == 0 {
+                    cell.shooter = NO_SHOOTER;
+                }
+                return;
+            }
+            (prev, at) = (Some(at), self.next_shooter[at] as usize);
+        }
+    }
+
+    pub fn add_projectile(&mut self, x: usize, y: usize, slot: usize) {
+        let Some(cell) = self.cells.get_mut(y).and_then(|row| row.get_mut(x)) else {
+            return;
+        };
+        self.next_projectile[slot] = cell.projectile;
+        cell.projectile = slot as u16;
+        cell.projectiles += 1;
+    }
+
+    pub fn remove_projectiles(&mut self, x: usize, y: usize) {
+        if let Some(cell) = self.cell_mut(x, y) {
+            cell.projectiles = 0;
+        }
+    }
+
+    /// Forgets every projectile, as when a bomb goes off.
+    pub fn clear_projectiles(&mut self) {
+        for cell in self.cells.iter_mut().flatten() {
+            cell.projectiles = 0;
+        }
+    }
+
+    pub fn add_pickup(&mut self, x: usize, y: usize, pickup: Pickup) {
+        if let Some(cell) = self.cell_mut(x, y) {
+            cell.pickup = pickup.code();
+        }
+    }
+
+    pub fn remove_pickup(&mut self, x: usize, y: usize) {
+        if let Some(cell) = self.cell_mut(x, y) {
+            cell.pickup = NO_PICKUP;
+        }
+    }
+
+    /// Whether the cell is solid wall.
+    pub fn wall(&self, x: usize, y: usize) -> bool {
+        self.cell(x, y).is_none_or(|cell| cell.wall)
+    }
+
+    /// How many shooters are in the cell.
+    pub fn shooters(&self, x: usize, y: usize) -> usize {
+        self.cell(x, y).map_or(0, |cell| cell.shooters as usize)
+    }
+
+    /// The registry slot of one of the shooters in the cell, the last one added.
+    pub fn shooter(&self, x: usize, y: usize) -> Option<usize> {
+        self.cell(x, y).filter(|cell| cell.shooters > 0).map(|cell| cell.shooter as usize)
+    }
+
+    /// How many enemy projectiles are in the cell.
+    pub fn projectiles(&self, x: usize, y: usize) -> usize {
+        self.cell(x, y).map_or(0, |cell| cell.projectiles as usize)
+    }
+
+    /// The pool slot of one of the projectiles in the cell, the last one added.
+    pub fn projectile(&self, x: usize, y: usize) -> Option<usize> {
+        self.cell(x, y).filter(|cell| cell.projectiles > 0).map(|cell| cell.projectile as usize)
+    }
+
+    /// The pool slots of every projectile in the cell, last added first.
+    pub fn projectile_slots(&self, x: usize, y: usize) -> impl Iterator<Item = usize> + '_ {
+        let mut slot = self.projectile(x, y).unwrap_or(0);
+        (0..self.projectiles(x, y)).map(move |i| {
+            if i > 0 {
+                slot = self.next_projectile[slot] as usize;
+            }
+            slot
+        })
+    }
+
+    pub fn pickup(&self, x: usize, y: usize) -> Option<Pickup> {
+        self.cell(x, y).and_then(|cell| Pickup::from_code(cell.pickup))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Grid = Occupancy<16, 8>;
+
+    #[test]
+    fn chains_projectiles_sharing_a_cell() {
+        let mut grid = Grid::new();
+        for slot in [4, 9, 2] {
+            grid.add_projectile(3, 3, slot);
+        }
+        grid.add_projectile(4, 3, 7);
+        assert_eq!(grid.projectiles(3, 3), 3);
+        assert_eq!(grid.projectile_slots(3, 3).collect::<Vec<_>>(), [2, 9, 4]);
+        assert_eq!(grid.projectile_slots(4, 3).collect::<Vec<_>>(), [7]);
+        grid.remove_projectiles(3, 3);
+        assert_eq!(grid.projectile_slots(3, 3).count(), 0);
+        assert_eq!(grid.projectile(4, 3), Some(7));
+    }
+
+    #[test]
+    fn removes_a_shooter_from_anywhere_in_its_cell() {
+        let mut grid = Grid::new();
+        for slot in [1, 5, 3] {
+            grid.add_shooter(2, 2, slot);
+        }
+        grid.remove_shooter(2, 2, 5);
+        assert_eq!((grid.shooters(2, 2), grid.shooter(2, 2)), (2, Some(3)));
+        grid.remove_shooter(2, 2, 6);
+        assert_eq!(grid.shooters(2, 2), 2);
+        grid.remove_shooter(2, 2, 3);
+        assert_eq!((grid.shooters(2, 2), grid.shooter(2, 2)), (1, Some(1)));
+        grid.remove_shooter(2, 2, 1);
+        assert_eq!((grid.shooters(2, 2), grid.shooter(2, 2)), (0, None));
+    }
+
+    #[test]
+    fn walls_outlast_a_clear() {
+        let mut grid = Grid::new();
+        grid.add_wall(5, 1);
+        grid.add_shooter(5, 1, 0);
+        grid.add_pickup(6, 1, Pickup::Junk(2));
+        grid.clear();
+        assert!(grid.wall(5, 1) && !grid.wall(6, 1));
+        assert_eq!((grid.shooter(5, 1), grid.pickup(6, 1)), (None, None));
+        assert!(grid.wall(BUFFER_WIDTH, 1) && grid.wall(0, BUFFER_HEIGHT));
+        grid.clear_walls();
+        assert!(!grid.wall(5, 1));
+    }
+}
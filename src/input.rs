@@ -0,0 +1,118 @@
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use pc_keyboard::DecodedKey;
+
+/// A fixed-size ring buffer of key events with one producer and one consumer.
+///
+/// The keyboard interrupt handler pushes and `cpu_loop` pops, so neither side ever waits on
+/// a lock the other might hold. When the buffer is full the newest key is dropped and
+/// counted, so the keys that do arrive are always delivered in the order they were typed.
+///
+/// The slots are plain cells. A slot between `head` and `tail` belongs to the consumer and
+/// any other belongs to the producer; each index is published with a Release store and read
+/// with an Acquire load, so a slot's contents are always visible before it changes hands.
+pub struct InputQueue<const N: usize> {
+    slots: [UnsafeCell<Option<DecodedKey>>; N],
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    overflows: AtomicUsize,
+}
+
+// Safety: `push` is only ever called from one context and `pop` from one other, and the
+// head/tail handoff above keeps them off each other's slots.
+unsafe impl<const N: usize> Sync for InputQueue<N> {}
+
+impl<const N: usize> InputQueue<N> {
+    pub const fn new() -> Self {
+        Self {slots: [const { UnsafeCell::new(None) }; N], head: AtomicUsize::new(0), tail: AtomicUsize::new(0), overflows: AtomicUsize::new(0)}
+    }
+
+    /// Adds a key to the back of the queue. Returns false, and counts an overflow,
+    /// if the queue is full.
+    pub fn push(&self, key: DecodedKey) -> bool {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) >= N {
+            self.overflows.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        // Safety: the slot is outside head..tail, so only the producer touches it until the
+        // store to `tail` below hands it over.
+        unsafe { *self.slots[tail % N].get() = Some(key) };
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// Removes the oldest key, if there is one.
+    pub fn pop(&self) -> Option<DecodedKey> {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        // Safety: the slot is inside head..tail, so the producer won't write it again until
+        // the store to `head` below hands it back.
+        let key = unsafe { (*self.slots[head % N].get()).take() };
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        key
+    }
+
+    pub fn len(&self) -> usize {
+        self.tail.load(Ordering::Acquire).wrapping_sub(self.head.load(Ordering::Acquire))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// How many keys have been dropped because the queue was full.
+    pub fn overflows(&self) -> usize {
+        self.overflows.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_order_and_counts_overflows() {
+        let queue: InputQueue<4> = InputQueue::new();
+        for c in "abcde".chars() {
+            queue.push(DecodedKey::Unicode(c));
+        }
+        assert_eq!(queue.len(), 4);
+        assert_eq!(queue.overflows(), 1);
+        let popped: Vec<_> = core::iter::from_fn(|| queue.pop()).collect();
+        assert_eq!(popped, "abcd".chars().map(DecodedKey::Unicode).collect::<Vec<_>>());
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn hands_keys_across_threads() {
+        let queue: InputQueue<8> = InputQueue::new();
+        let keys = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h'];
+        let received = std::thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..10_000 {
+                    while !queue.push(DecodedKey::Unicode(keys[i % keys.len()])) {
+                        std::thread::yield_now();
+                    }
+                }
+            });
+            let mut received = Vec::new();
+            while received.len() < 10_000 {
+                match queue.pop() {
+                    Some(key) => received.push(key),
+                    None => std::thread::yield_now(),
+                }
+            }
+            received
+        });
+        assert!(received.iter().enumerate().all(|(i, key)| *key == DecodedKey::Unicode(keys[i % keys.len()])));
+    }
+}
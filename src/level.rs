@@ -0,0 +1,340 @@
+use core::fmt;
+use crate::platform::{BUFFER_WIDTH, BUFFER_HEIGHT};
+use crate::{Walls, PowerKind, Tile};
+
+// A level file is a header of `key: value` lines, a `---` line, then the map in the
+// same grid that `Walls::new` reads: `#` wall, `%` brittle wall, `>` `v` `<` `^` one-way
+// gates, `1`-`9` teleporter pairs, `~` ice, `:` slow ground, space for floor. Header keys:
+//
+//   name: Pillars          shown on the wave banner
+//   par: 120               seconds to clear the level for a time bonus
+//   waves: 3               waves to survive before moving on to the next level
+//   start: 40,12           where the player appears
+//   spawn: 5,3             a cell shooters may appear on (repeatable)
+//   pickup: 28,12,slow     a power-up waiting on the map at the start (repeatable)
+//
+// Everything except the map itself is optional.
+
+pub const MAX_SPAWNS: usize = 8;
+pub const MAX_PICKUPS: usize = 8;
+
+pub const LEVELS: [&str; 3] = [
+    include_str!("../levels/open_field.txt"),
+    include_str!("../levels/pillars.txt"),
+    include_str!("../levels/corridors.txt"),
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LevelError {
+    MissingSeparator,
+    UnknownKey(usize),
+    BadValue(usize),
+    TooManySpawns(usize),
+    TooManyPickups(usize),
+    MapTooTall(usize),
+    MapTooWide(usize),
+    UnknownMapChar(usize, char),
+    UnpairedTeleporter(char),
+    StartInWall,
+    SpawnInWall(usize),
+    PickupInWall(usize),
+}
+
+impl LevelError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            LevelError::MissingSeparator => "no --- line before the map",
+            LevelError::UnknownKey(_) => "unknown header key",
+            LevelError::BadValue(_) => "malformed header value",
+            LevelError::TooManySpawns(_) => "too many spawn points",
+            LevelError::TooManyPickups(_) => "too many pickups",
+            LevelError::MapTooTall(_) => "map has more than 25 rows",
+            LevelError::MapTooWide(_) => "map row longer than 80 columns",
+            LevelError::UnknownMapChar(_, _) => "unknown character in map",
+            LevelError::UnpairedTeleporter(_) => "teleporter does not appear exactly twice",
+            LevelError::StartInWall => "player start is inside a wall",
+            LevelError::SpawnInWall(_) => "spawn point is inside a wall",
+            LevelError::PickupInWall(_) => "pickup is inside a wall",
+        }
+    }
+
+    /// The line of the level text the error was found on, counting from 1.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            LevelError::MissingSeparator | LevelError::StartInWall | LevelError::UnpairedTeleporter(_)
+            | LevelError::SpawnInWall(_) | LevelError::PickupInWall(_) => None,
+            LevelError::UnknownKey(line) | LevelError::BadValue(line) | LevelError::TooManySpawns(line)
+            | LevelError::TooManyPickups(line) | LevelError::MapTooTall(line) | LevelError::MapTooWide(line)
+            | LevelError::UnknownMapChar(line, _) => Some(*line),
+        }
+    }
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line() {
+            Some(line) => write!(f, "line {}: {}", line, self.message())?,
+            None => write!(f, "{}", self.message())?,
+        }
+        match self {
+            LevelError::UnknownMapChar(_, c) | LevelError::UnpairedTeleporter(c) => write!(f, " ({:?})", c),
+            LevelError::SpawnInWall(n) => write!(f, " (spawn {})", n),
+            LevelError::PickupInWall(n) => write!(f, " (pickup {})", n),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Level {
+    pub name: &'static str,
+    pub par: isize,
+    pub waves: isize,
+    pub start: (usize, usize),
+    pub spawns: [(usize, usize); MAX_SPAWNS],
+    pub spawn_count: usize,
+    pub pickups: [(usize, usize, PowerKind); MAX_PICKUPS],
+    pub pickup_count: usize,
+    pub walls: Walls,
+}
+
+impl Level {
+    /// A plain bordered arena with no spawn points, used when a level will not parse.
+    pub fn fallback() -> Self {
+        Self {name: "Arena", par: 0, waves: 3, start: (BUFFER_WIDTH / 2, BUFFER_HEIGHT / 2),
+            spawns: [(0, 0); MAX_SPAWNS], spawn_count: 0, pickups: [(0, 0, PowerKind::Shield); MAX_PICKUPS],
+            pickup_count: 0, walls: Walls::new(crate::WALLS)}
+    }
+
+    pub fn parse(text: &'static str) -> Result<Self, LevelError> {
+        let mut level = Self::fallback();
+        level.name = "Untitled";
+        let mut lines = text.split('\n').map(|line| line.trim_end_matches('\r')).enumerate();
+        let mut map_start = None;
+        for (i, line) in lines.by_ref() {
+            let line_num = i + 1;
+            if line.trim() == "---" {
+                map_start = Some(line_num);
+                break;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            if line.starts_with('#') {
+                return Err(LevelError::MissingSeparator);
+            }
+            let (key, value) = line.split_once(':').ok_or(LevelError::BadValue(line_num))?;
+            let value = value.trim();
+            match key.trim() {
+                "name" => level.name = value,
+                "par" => level.par = value.parse().map_err(|_| LevelError::BadValue(line_num))?,
+                "waves" => level.waves = value.parse().map_err(|_| LevelError::BadValue(line_num))?,
+                "start" => level.start = parse_point(value).ok_or(LevelError::BadValue(line_num))?,
+                "spawn" => {
+                    if level.spawn_count == MAX_SPAWNS {
+                        return Err(LevelError::TooManySpawns(line_num));
+                    }
+                    level.spawns[level.spawn_count] = parse_point(value).ok_or(LevelError::BadValue(line_num))?;
+                    level.spawn_count += 1;
+                }
+                "pickup" => {
+                    if level.pickup_count == MAX_PICKUPS {
+                        return Err(LevelError::TooManyPickups(line_num));
+                    }
+                    let (point, kind) = value.rsplit_once(',').ok_or(LevelError::BadValue(line_num))?;
+                    let (x, y) = parse_point(point).ok_or(LevelError::BadValue(line_num))?;
+                    let kind = PowerKind::from_name(kind.trim()).ok_or(LevelError::BadValue(line_num))?;
+                    level.pickups[level.pickup_count] = (x, y, kind);
+                    level.pickup_count += 1;
+                }
+                _ => return Err(LevelError::UnknownKey(line_num)),
+            }
+        }
+        let map_start = map_start.ok_or(LevelError::MissingSeparator)?;
+
+        let mut walls = [[Tile::Floor; BUFFER_WIDTH]; BUFFER_HEIGHT];
+        for (i, line) in lines {
+            let row = i - map_start;
+            if row >= BUFFER_HEIGHT {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                return Err(LevelError::MapTooTall(i + 1));
+            }
+            if line.chars().count() > BUFFER_WIDTH {
+                return Err(LevelError::MapTooWide(i + 1));
+            }
+            for (col, c) in line.chars().enumerate() {
+                walls[row][col] = Tile::from_char(c).ok_or(LevelError::UnknownMapChar(i + 1, c))?;
+            }
+        }
+        level.walls = Walls::from_grid(walls);
+        level.validate()?;
+        Ok(level)
+    }
+
+    /// Checks what a well-formed level can still get wrong: every teleporter needs exactly one
+    /// partner, and the start, spawn points and pickups must all be clear of walls.
+    pub fn validate(&self) -> Result<(), LevelError> {
+        let mut teleporters = [0; 10];
+        for row in 0..BUFFER_HEIGHT {
+            for col in 0..BUFFER_WIDTH {
+                if let Tile::Teleporter(id) = self.walls.tile(row, col) {
+                    teleporters[id as usize] += 1;
+                }
+            }
+        }
+        if let Some(id) = teleporters.iter().position(|count| *count != 0 && *count != 2) {
+            return Err(LevelError::UnpairedTeleporter((b'0' + id as u8) as char));
+        }
+        if self.walls.occupied(self.start.1, self.start.0) {
+            return Err(LevelError::StartInWall);
+        }
+        if let Some(i) = self.spawns().iter().position(|(x, y)| self.walls.occupied(*y, *x)) {
+            return Err(LevelError::SpawnInWall(i + 1));
+        }
+        if let Some(i) = self.pickups().iter().position(|(x, y, _)| self.walls.occupied(*y, *x)) {
+            return Err(LevelError::PickupInWall(i + 1));
+        }
+        Ok(())
+    }
+
+    pub fn spawns(&self) -> &[(usize, usize)] {
+        &self.spawns[..self.spawn_count]
+    }
+
+    pub fn pickups(&self) -> &[(usize, usize, PowerKind)] {
+        &self.pickups[..self.pickup_count]
+    }
+
+    pub fn pickup_at(&self, x: usize, y: usize) -> Option<PowerKind> {
+        self.pickups().iter().find(|(tx, ty, _)| *tx == x && *ty == y).map(|(_, _, kind)| *kind)
+    }
+
+    pub fn is_spawn(&self, x: usize, y: usize) -> bool {
+        self.spawns().contains(&(x, y))
+    }
+
+    /// Adds a spawn point at the given cell, or removes the one already there.
+    /// Returns false if the spawn list is full.
+    pub fn toggle_spawn(&mut self, x: usize, y: usize) -> bool {
+        if let Some(i) = self.spawns().iter().position(|p| *p == (x, y)) {
+            self.spawn_count -= 1;
+            self.spawns[i] = self.spawns[self.spawn_count];
+            true
+        } else if self.spawn_count < MAX_SPAWNS {
+            self.spawns[self.spawn_count] = (x, y);
+            self.spawn_count += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Steps the pickup at the given cell through none, shield, slow and bomb.
+    /// Returns false if a new pickup was needed but the pickup list is full.
+    pub fn cycle_pickup(&mut self, x: usize, y: usize) -> bool {
+        match self.pickups().iter().position(|(tx, ty, _)| *tx == x && *ty == y) {
+            Some(i) => {
+                match self.pickups[i].2 {
+                    PowerKind::Shield => self.pickups[i].2 = PowerKind::SlowTime,
+                    PowerKind::SlowTime => self.pickups[i].2 = PowerKind::Bomb,
+                    PowerKind::Bomb => {
+                        self.pickup_count -= 1;
+                        self.pickups[i] = self.pickups[self.pickup_count];
+                    }
+                }
+                true
+            }
+            None if self.pickup_count < MAX_PICKUPS => {
+                self.pickups[self.pickup_count] = (x, y, PowerKind::Shield);
+                self.pickup_count += 1;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// Writes the level back out in the text format `Level::parse` reads.
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "name: {}", self.name)?;
+        writeln!(f, "par: {}", self.par)?;
+        writeln!(f, "waves: {}", self.waves)?;
+        writeln!(f, "start: {},{}", self.start.0, self.start.1)?;
+        for (x, y) in self.spawns() {
+            writeln!(f, "spawn: {},{}", x, y)?;
+        }
+        for (x, y, kind) in self.pickups() {
+            writeln!(f, "pickup: {},{},{}", x, y, kind.name())?;
+        }
+        writeln!(f, "---")?;
+        for row in 0..BUFFER_HEIGHT {
+            for col in 0..BUFFER_WIDTH {
+                write!(f, "{}", self.walls.tile(row, col).glyph())?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+// An `x,y` cell on the map. Anything off the map is as malformed as a missing number.
+fn parse_point(s: &str) -> Option<(usize, usize)> {
+    let (x, y) = s.split_once(',')?;
+    let (x, y) = (x.trim().parse().ok()?, y.trim().parse().ok()?);
+    (x < BUFFER_WIDTH && y < BUFFER_HEIGHT).then_some((x, y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_levels_parse() {
+        for text in LEVELS {
+            let level = Level::parse(text).unwrap();
+            assert_ne!(level.name, "Untitled");
+            assert!(level.spawn_count > 0 && level.pickup_count > 0);
+        }
+        let pillars = Level::parse(LEVELS[1]).unwrap();
+        assert_eq!(pillars.pickup_at(28, 12), Some(PowerKind::SlowTime));
+    }
+
+    #[test]
+    fn header_errors_give_their_line() {
+        let cases = [
+            ("name: Bad\npar fast\n---\n", LevelError::BadValue(2)),
+            ("name: Bad\n\nwaves: many\n---\n", LevelError::BadValue(3)),
+            ("name: Bad\ncolor: red\n---\n", LevelError::UnknownKey(2)),
+            ("pickup: 3,3,laser\n---\n", LevelError::BadValue(1)),
+            ("name: Bad\nstart: 90,5\n---\n", LevelError::BadValue(2)),
+            ("spawn: 5,25\n---\n", LevelError::BadValue(1)),
+            ("name: Bad\n#####\n", LevelError::MissingSeparator),
+            ("---\n  ?\n", LevelError::UnknownMapChar(2, '?')),
+        ];
+        for (text, error) in cases {
+            assert_eq!(Level::parse(text).err(), Some(error), "{:?}", text);
+        }
+        let e = Level::parse("name: Bad\ncolor: red\n---\n").err().unwrap();
+        assert_eq!(e.line(), Some(2));
+        assert_eq!(format!("{}", e), "line 2: unknown header key");
+    }
+
+    #[test]
+    fn map_errors_name_what_is_wrong() {
+        let cases = [
+            ("start: 1,1\n---\n###\n###\n", LevelError::StartInWall, "player start is inside a wall"),
+            ("start: 5,5\nspawn: 6,5\nspawn: 1,0\n---\n##\n", LevelError::SpawnInWall(2), "spawn point is inside a wall (spawn 2)"),
+            ("start: 5,5\npickup: 0,0,bomb\n---\n#\n", LevelError::PickupInWall(1), "pickup is inside a wall (pickup 1)"),
+            ("start: 5,5\n---\n 1 1 3\n", LevelError::UnpairedTeleporter('3'), "teleporter does not appear exactly twice ('3')"),
+        ];
+        for (text, error, message) in cases {
+            let e = Level::parse(text).err().unwrap();
+            assert_eq!(e, error);
+            assert_eq!(e.line(), None);
+            assert_eq!(format!("{}", e), message);
+        }
+    }
+}
@@ -1,15 +1,75 @@
 #![cfg_attr(not(test), no_std)]
+// Everything is built with `new()`, most of it in consts and statics, so Default would go unused.
+#![allow(clippy::new_without_default)]
 
-use pluggable_interrupt_os::vga_buffer::{BUFFER_WIDTH, BUFFER_HEIGHT, plot, ColorCode, Color, plot_num, plot_str};
-use pc_keyboard::{DecodedKey, KeyCode};
+pub mod platform;
+pub mod canvas;
+pub mod level;
+pub mod editor;
+pub mod input;
+pub mod controls;
+pub mod menu;
+pub mod scores;
+pub mod nvram;
+pub mod link;
+pub mod netplay;
+pub mod pool;
+pub mod grid;
+pub mod seed;
+pub mod replay;
+
+use platform::{BUFFER_WIDTH, BUFFER_HEIGHT, ColorCode, Color};
+use pc_keyboard::DecodedKey;
 use rand::SeedableRng;
 use rand::rngs::SmallRng;
 use rand::RngCore;
+use canvas::Canvas;
+use level::{Level, LevelError, LEVELS};
+use editor::{Editor, EditorAction};
+use controls::{Action, Bindings, ControlsMenu, plot_key};
+use menu::Menu;
+use scores::{Score, ScoreTable, InitialsEntry};
+use link::SerialLink;
+use netplay::{Session, LinkState, LinkEvent, LinkEnd, Hello, HASH_INTERVAL};
+use pool::{ProjectilePool, ShooterRegistry, PoolStats};
+use grid::{Occupancy, Pickup};
+use seed::SeedEntry;
+use replay::{Replay, Playback, ReplayExport, ReplayImport, ReplayError};
+use platform::serial_println;
 
 // Mostly from Dr. Ferrer in class 3/13 and 3/15
 
-const ADD_SHOOTER_FREQ: isize = 20;
 const MOVE_SHOOT_FREQ: isize = 5;
+const MOVE_PROJ_FREQ: isize = 2;
+const STARTING_LIVES: isize = 3;
+const INVULNERABLE_TICKS: isize = 30;
+const ADD_POWER_UP_FREQ: isize = 150;
+const WAVE_BANNER_TICKS: isize = 60;
+const WAVE_BONUS: isize = 200;
+const PAR_BONUS: isize = 1000;
+const TICKS_PER_SECOND: isize = 18;
+const SURVIVAL_POINTS: isize = 5;
+const ADD_JUNK_FREQ: isize = 45;
+const JUNK_DRIFT_FREQ: isize = 6;
+const COMBO_WINDOW: isize = 90;
+const MAX_COMBO: isize = 8;
+const SEED: u32 = 6;
+const MAX_PROJECTILES: usize = 1000;
+const MAX_SHOOTERS: usize = 64;
+// How many bytes of a replay go out each time round the main loop.
+const REPLAY_CHUNK: usize = 64;
+
+// Projectile directions: right, down, left, up, then the diagonals clockwise from down-right.
+const DIRECTIONS: [(isize, isize); 8] = [(1, 0), (0, 1), (-1, 0), (0, -1), (1, 1), (-1, 1), (-1, -1), (1, -1)];
+
+// Relative odds of each kind of shooter appearing. Early waves only use the first few kinds.
+const SHOOTER_WEIGHTS: [(ShooterKind, u32); 5] = [
+    (ShooterKind::Basic, 40),
+    (ShooterKind::Spreader, 20),
+    (ShooterKind::Sniper, 15),
+    (ShooterKind::Spiral, 15),
+    (ShooterKind::Burst, 10),
+];
 
 const WALLS: &str = "################################################################################
 #                                                                              #
@@ -37,351 +97,2637 @@ const WALLS: &str = "###########################################################
 #                                                                              #
 ################################################################################";
 
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum Status {
+    Title,
     Normal,
+    Paused,
+    LevelComplete,
+    EnterInitials,
     Over,
+    HighScores,
+    Settings,
+    Editor,
+    Controls,
+    Connecting,
+    SeedEntry,
+    Replays,
+}
+
+/// Who is playing: one player, two sharing the keyboard and either pooling their score or
+/// each keeping their own, or two rivals on linked machines.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PlayMode {
+    Solo,
+    CoOp,
+    Competitive,
+    /// Rivals on two machines joined by a serial cable, one player each.
+    Linked,
+}
+
+impl PlayMode {
+    pub fn players(&self) -> usize {
+        match self {
+            PlayMode::Solo => 1,
+            PlayMode::CoOp | PlayMode::Competitive | PlayMode::Linked => 2,
+        }
+    }
+
+    /// Whether each player keeps their own score.
+    pub fn rivals(&self) -> bool {
+        matches!(self, PlayMode::Competitive | PlayMode::Linked)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PlayMode::Solo => "1 player",
+            PlayMode::CoOp => "2 players, co-op",
+            PlayMode::Competitive => "2 players, rivals",
+            PlayMode::Linked => "2 players, linked",
+        }
+    }
+
+    fn step(&self, by: isize) -> Self {
+        // A linked game is started from the title screen, so it isn't one of the settings.
+        const MODES: [PlayMode; 3] = [PlayMode::Solo, PlayMode::CoOp, PlayMode::Competitive];
+        let i = MODES.iter().position(|m| m == self).unwrap_or(0) as isize;
+        MODES[(i + by).rem_euclid(MODES.len() as isize) as usize]
+    }
+}
+
+// Each player's glyph and color.
+const PLAYER_LOOKS: [(char, Color); 2] = [('*', Color::Green), ('=', Color::LightBlue)];
+
+const TITLE_ITEMS: [&str; 7] = ["Start game", "Play a seed", "Replays", "Versus over serial", "Level editor", "High scores", "Settings"];
+const PAUSE_ITEMS: [&str; 4] = ["Resume", "Restart", "Controls", "Quit to title"];
+const REPLAY_ITEMS: [&str; 4] = ["Watch", "Send over serial", "Receive over serial", "Back"];
+const SETTINGS_ITEMS: [&str; 6] = ["Lives", "First level", "Players", "Restart seed", "Controls", "Back"];
+
+/// The choices that outlast a run: they are kept when a new game starts.
+#[derive(Copy, Clone)]
+pub struct Settings {
+    pub lives: isize,
+    pub level: usize,
+    pub mode: PlayMode,
+    pub bindings: Bindings,
+    /// The second player's keys. These are checked first in a two-player game.
+    pub second: Bindings,
+    /// The seed the next run starts from.
+    pub seed: u32,
+    /// Whether a new run rolls a new seed, rather than replaying the last one.
+    pub reroll: bool,
+    // The seed of the run started last, if it came from `seed`.
+    last_seed: Option<u32>,
+}
+
+impl Settings {
+    pub fn new() -> Self {
+        Self {lives: STARTING_LIVES, level: 0, mode: PlayMode::Solo, bindings: Bindings::standard(),
+            second: Bindings::second_player(), seed: SEED, reroll: true, last_seed: None}
+    }
 }
 
 // Modified from class on 3/13
 pub struct Game {
-    player: Player,
+    players: [Player; 2],
     walls: Walls,
     tick_count: isize,
-    shooters: [Shooter; 100],
-    projectiles: [Projectile; 1000],
-    proj_count: isize,
-    shot_count: isize,
+    shooters: ShooterRegistry<MAX_SHOOTERS>,
+    grid: Occupancy<MAX_PROJECTILES, MAX_SHOOTERS>,
+    projectiles: ProjectilePool<MAX_PROJECTILES>,
+    player_projectiles: [[Projectile; 8]; 2],
     // From https://stackoverflow.com/questions/67627335/how-do-i-use-the-rand-crate-without-the-standard-library
     rng: SmallRng,
     status: Status,
-    active_shooters: isize,
-    drawn_proj: isize,
+    score: [isize; 2],
+    junk: [Junk; 8],
+    junk_collected: isize,
+    combo: isize,
+    last_pickup: isize,
+    lives: [isize; 2],
+    power_ups: [PowerUp; 4],
+    active_power: Option<PowerKind>,
+    power_until: isize,
+    wave: isize,
+    wave_start: isize,
+    wave_spawned: usize,
+    banner: isize,
+    level: usize,
+    level_info: Level,
+    level_start: isize,
+    level_first_wave: isize,
+    level_error: Option<LevelError>,
+    level_seconds: isize,
+    level_bonus: isize,
+    // The level Confirm starts from the game-over screen.
+    picked_level: usize,
+    editor: Editor,
+    play_testing: bool,
+    settings: Settings,
+    scores: ScoreTable,
+    scores_changed: bool,
+    confirm_clear: bool,
+    initials: InitialsEntry,
+    seed_entry: SeedEntry,
+    seed: u32,
+    title: Menu,
+    pause: Menu,
+    settings_menu: Menu,
+    controls: ControlsMenu,
+    menu_return: Status,
+    mode: PlayMode,
+    net: Session,
+    // The run being recorded, or the last one recorded or received.
+    replay: Option<Replay>,
+    recording: bool,
+    playback: Option<Playback>,
+    replay_menu: Menu,
+    export: Option<ReplayExport>,
+    import: Option<ReplayImport>,
+    replay_message: &'static str,
 }
 
 impl Game {
+    /// Boots to the title screen.
     pub fn new() -> Self {
-        Self {player: Player::new(), walls: Walls::new(WALLS), tick_count: 0, 
-            shooters: [Shooter::new(); 100], projectiles: [Projectile::new(); 1000], 
-            proj_count: 0, shot_count: 0, rng: SmallRng::seed_from_u64(6), status: Status::Normal, 
-            active_shooters: 0, drawn_proj: 50}
+        let mut game = Self::fresh(Settings::new(), Editor::new(Level::fallback()), ScoreTable::new());
+        game.status = Status::Title;
+        game
+    }
+
+    pub fn with_lives(lives: isize) -> Self {
+        let mut game = Self::new();
+        game.settings.lives = lives;
+        game.lives = [lives; 2];
+        game
+    }
+
+    // A run that hasn't started yet, on the settings' first level.
+    fn fresh(settings: Settings, editor: Editor, scores: ScoreTable) -> Self {
+        let mut game = Self {players: [Player::new(); 2], walls: Walls::new(WALLS), tick_count: 0,
+            shooters: ShooterRegistry::new(), grid: Occupancy::new(), projectiles: ProjectilePool::new(),
+            player_projectiles: [[Projectile::new(); 8]; 2], rng: SmallRng::seed_from_u64(settings.seed as u64),
+            status: Status::Normal,
+            score: [0; 2], junk: [Junk::new(); 8], junk_collected: 0, combo: 0, last_pickup: 0,
+            lives: [settings.lives; 2],
+            power_ups: [PowerUp::new(); 4], active_power: None, power_until: 0,
+            wave: 1, wave_start: 0, wave_spawned: 0, banner: WAVE_BANNER_TICKS,
+            level: 0, level_info: Level::fallback(), level_start: 0, level_first_wave: 1, level_error: None,
+            level_seconds: 0, level_bonus: 0, picked_level: settings.level, editor, play_testing: false,
+            settings, scores, scores_changed: false, confirm_clear: false,
+            initials: InitialsEntry::new(), seed_entry: SeedEntry::new(), seed: settings.seed,
+            title: Menu::new(&TITLE_ITEMS), pause: Menu::new(&PAUSE_ITEMS), settings_menu: Menu::new(&SETTINGS_ITEMS),
+            controls: ControlsMenu::new(), menu_return: Status::Title,
+            mode: settings.mode, net: Session::new(),
+            replay: None, recording: false, playback: None,
+            replay_menu: Menu::new(&REPLAY_ITEMS), export: None, import: None, replay_message: ""};
+        game.load_level(settings.level);
+        game
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    /// The score that goes on the high-score table: the shared score in co-op,
+    /// the winner's in a competitive game.
+    pub fn score(&self) -> isize {
+        self.score[0].max(self.score[1])
+    }
+
+    pub fn set_mode(&mut self, mode: PlayMode) {
+        self.settings.mode = mode;
+        self.mode = mode;
+    }
+
+    /// The seed the current run started from.
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    /// The run being recorded, or the last one recorded or received.
+    pub fn replay(&self) -> Option<&Replay> {
+        self.replay.as_ref()
+    }
+
+    /// Makes the next run start from this seed, e.g. one read from the hardware at boot.
+    pub fn set_seed(&mut self, seed: u32) {
+        self.settings.seed = seed;
+        self.settings.last_seed = None;
+        self.seed = seed;
+        self.rng = SmallRng::seed_from_u64(seed as u64);
+    }
+
+    /// Replaces the high-score table, e.g. with the one saved in CMOS at boot.
+    pub fn set_scores(&mut self, scores: ScoreTable) {
+        self.scores = scores;
+    }
+
+    /// Returns the high-score table if it has changed since the last call, so it can be saved.
+    pub fn scores_to_save(&mut self) -> Option<ScoreTable> {
+        if self.scores_changed {
+            self.scores_changed = false;
+            Some(self.scores)
+        } else {
+            None
+        }
     }
 
+    // Editor, controls-menu and initials keys are handled as typed; everything else goes
+    // through the bindings.
     pub fn key(&mut self, key: DecodedKey) {
         match self.status {
-            Status::Normal => match key {
-                DecodedKey::RawKey(key) => {
-                    let mut future = self.player;
-                    match key {
-                        KeyCode::ArrowDown => {
-                            future.down();
-                        }
-                        KeyCode::ArrowLeft => {
-                            future.left();
-                        }
-                        KeyCode::ArrowRight => {
-                            future.right();
-                        }
-                        KeyCode::ArrowUp => {
-                            future.up();
-                        }
-                        KeyCode::R => {
-                            self.reset_game();
-                        }
-                        _ => {}
+            Status::EnterInitials => {
+                if self.initials.key(key) {
+                    let score = Score {initials: self.initials.initials(), points: self.score(), seed: self.seed};
+                    self.scores_changed |= self.scores.insert(score).is_some();
+                    self.status = Status::Over;
+                }
+            }
+            Status::Editor => match self.editor.key(key) {
+                EditorAction::PlayTest => self.play_test(),
+                EditorAction::Quit => self.enter(Status::Title),
+                EditorAction::Stay => {}
+            },
+            Status::Controls => {
+                if self.controls.key(key, &mut self.settings.bindings) {
+                    self.status = self.menu_return;
+                }
+            }
+            Status::SeedEntry => {
+                if self.settings.bindings.action(key) == Some(Action::Back) {
+                    self.status = Status::Title;
+                } else if self.seed_entry.key(key) {
+                    if let Some(seed) = self.seed_entry.value() {
+                        self.set_seed(seed);
+                        self.start_run();
                     }
-                    if !future.is_colliding(&self.walls) {
-                        self.player = future;
+                }
+            }
+            _ => {
+                if self.status == Status::Normal && self.mode.players() > 1 && self.mode != PlayMode::Linked {
+                    if let Some(action) = self.settings.second.action(key) {
+                        self.input(1, action);
+                        return;
                     }
-                },
-                DecodedKey::Unicode(_) => {}
+                }
+                if let Some(action) = self.settings.bindings.action(key) {
+                    self.action(action);
+                }
+            }
+        }
+    }
+
+    pub fn action(&mut self, action: Action) {
+        match self.status {
+            Status::Title => match self.title.action(action) {
+                Some(0) => self.start_run(),
+                Some(1) => {
+                    self.seed_entry = SeedEntry::new();
+                    self.status = Status::SeedEntry;
+                }
+                Some(2) => {
+                    self.replay_message = "";
+                    self.status = Status::Replays;
+                }
+                Some(3) => self.connect(),
+                Some(4) => self.open_editor(),
+                Some(5) => {
+                    self.confirm_clear = false;
+                    self.status = Status::HighScores;
+                }
+                Some(6) => self.status = Status::Settings,
+                _ => {}
             },
-            Status::Over => {
-                match key {
-                    DecodedKey::RawKey(KeyCode::S) | DecodedKey::Unicode('s') => self.reset_game(),
-                    _ => {}
+            Status::Normal if self.mode == PlayMode::Linked => match action {
+                Action::MoveRight | Action::MoveDown | Action::MoveLeft | Action::MoveUp | Action::Fire => {
+                    self.net.queue(action)
+                }
+                Action::Back => self.leave_match(),
+                _ => {}
+            },
+            Status::Normal => match action {
+                Action::MoveRight | Action::MoveDown | Action::MoveLeft | Action::MoveUp | Action::Fire => {
+                    self.input(0, action)
+                }
+                Action::Pause | Action::Back => {
+                    self.pause = Menu::new(&PAUSE_ITEMS);
+                    self.status = Status::Paused;
+                }
+                Action::Restart => self.start_run(),
+                Action::Controls => self.open_controls(),
+                _ => {}
+            },
+            Status::Paused => match (action, self.pause.action(action)) {
+                (Action::Pause | Action::Back, _) | (_, Some(0)) => self.status = Status::Normal,
+                (Action::Restart, _) | (_, Some(1)) => self.start_run(),
+                (Action::Controls, _) | (_, Some(2)) => self.open_controls(),
+                (_, Some(3)) => self.enter(Status::Title),
+                _ => {}
+            },
+            Status::LevelComplete => {
+                if matches!(action, Action::Confirm | Action::Fire) && self.playback.is_none() {
+                    self.record(0, Action::Confirm);
+                    self.next_level();
+                }
+            }
+            Status::Over if self.mode == PlayMode::Linked => {
+                if matches!(action, Action::Back | Action::Confirm | Action::Restart) {
+                    self.leave_match();
+                }
+            }
+            Status::Connecting => {
+                if action == Action::Back {
+                    self.leave_match();
+                }
+            }
+            Status::Over => match action {
+                Action::MoveLeft => self.picked_level = (self.picked_level + LEVELS.len() - 1) % LEVELS.len(),
+                Action::MoveRight => self.picked_level = (self.picked_level + 1) % LEVELS.len(),
+                Action::Confirm => {
+                    let level = self.picked_level;
+                    self.start_run();
+                    self.load_level(level);
+                    self.start_recording();
+                }
+                Action::Restart => self.start_run(),
+                Action::Back => self.enter(Status::Title),
+                Action::Edit => self.open_editor(),
+                Action::Controls => self.open_controls(),
+                _ => {}
+            },
+            Status::HighScores if self.confirm_clear => {
+                if action == Action::Confirm {
+                    self.scores.clear();
+                    self.scores_changed = true;
                 }
+                self.confirm_clear = false;
+            }
+            Status::HighScores => match action {
+                Action::Back | Action::Confirm => self.status = Status::Title,
+                Action::Restart => self.confirm_clear = true,
+                _ => {}
             },
+            Status::Settings => self.settings_action(action),
+            Status::Replays => self.replays_action(action),
+            Status::Editor | Status::Controls | Status::EnterInitials | Status::SeedEntry => {}
         }
-        
     }
 
-    pub fn reset_game(&mut self) {
-        self.status = Status::Normal;
-        self.player = Player::new();
-        self.walls = Walls::new(WALLS);
-        self.tick_count = 0;
-        self.shooters = [Shooter::new(); 100];
-        self.projectiles = [Projectile::new(); 1000];
-        self.proj_count = 0;
-        self.shot_count = 0;
-        self.rng = SmallRng::seed_from_u64(6);
-        self.active_shooters = 0;
-        self.drawn_proj = 50;
+    // A move or shot from the keyboard. It goes into the replay being recorded, and is ignored
+    // while a replay is playing back.
+    fn input(&mut self, player: usize, action: Action) {
+        if self.playback.is_none() && self.in_play(player) {
+            self.record(player, action);
+            self.player_action(player, action);
+        }
     }
 
-    pub fn add_proj_count(&mut self) {
-        self.proj_count += 1;
-        self.proj_count %= 250;
+    fn record(&mut self, player: usize, action: Action) {
+        if let (true, Some(replay)) = (self.recording, self.replay.as_mut()) {
+            if !replay.push(self.tick_count, player, action) {
+                serial_println!("Replay full after {} inputs; the rest of the run isn't recorded", replay.inputs());
+                self.recording = false;
+            }
+        }
     }
 
-    pub fn add_shot_count(&mut self) {
-        self.shot_count += 1;
-        self.shot_count %= 99;
+    // Moves or fires for one player. A player who is out of lives can't do either.
+    fn player_action(&mut self, player: usize, action: Action) {
+        if !self.in_play(player) {
+            return;
+        }
+        match action {
+            Action::MoveRight => self.move_player(player, 0),
+            Action::MoveDown => self.move_player(player, 1),
+            Action::MoveLeft => self.move_player(player, 2),
+            Action::MoveUp => self.move_player(player, 3),
+            Action::Fire => self.fire(player),
+            _ => {}
+        }
     }
 
-    pub fn tick(&mut self) {
-        if self.tick_count % ADD_SHOOTER_FREQ == 0 {
-            let nx = 1 + self.rng.next_u32() as usize % (BUFFER_WIDTH - 1);
-            self.shooters[self.shot_count as usize].move_to(nx, 3);
-            self.add_shot_count();
-            self.active_shooters += 1;
+    fn replays_action(&mut self, action: Action) {
+        if self.import.is_some() {
+            if action == Action::Back {
+                self.import = None;
+                self.replay_message = "Stopped listening";
+            }
+            return;
         }
-        self.tick_count += 1;
-        if self.tick_count % MOVE_SHOOT_FREQ == 0 {
-            for i in 0..self.active_shooters {
-                if self.shooters[i as usize].x > 2 {
-                    let x_dir = self.rng.next_u32() as usize % 2;
-                    if x_dir == 0 {
-                        self.shooters[i as usize].move_to(self.shooters[i as usize].x-1, self.shooters[i as usize].y+1);
-                    } else {
-                        self.shooters[i as usize].move_to(self.shooters[i as usize].x+1, self.shooters[i as usize].y+1);
-                    }
-                } else {
-                    self.shooters[i as usize].move_to(self.shooters[i as usize].x+1, self.shooters[i as usize].y+1);
+        match self.replay_menu.action(action) {
+            Some(0) => self.watch_replay(),
+            Some(1) => match &self.replay {
+                Some(replay) => {
+                    self.export = Some(ReplayExport::new(replay));
+                    self.replay_message = "Sending...";
                 }
+                None => self.replay_message = "No replay to send",
+            },
+            Some(2) => {
+                self.import = Some(ReplayImport::new());
+                self.replay_message = "";
             }
+            Some(3) => self.status = Status::Title,
+            _ if action == Action::Back => self.status = Status::Title,
+            _ => {}
         }
-        self.walls.draw();
-        plot('*', self.player.x, self.player.y, ColorCode::new(Color::Green, Color::Black));
-        for shootr in self.shooters {
-            if shootr.x < 80 && shootr.y < 25 {
-                shootr.draw();
-                self.projectiles[self.proj_count as usize] = shootr.shoot_down();
-                self.add_proj_count();
-                self.projectiles[self.proj_count as usize] = shootr.shoot_left();
-                self.add_proj_count();
-                self.projectiles[self.proj_count as usize] = shootr.shoot_up();
-                self.add_proj_count();
-                self.projectiles[self.proj_count as usize] = shootr.shoot_right();
-                self.add_proj_count();
+    }
+
+    // Starts the recorded run over from its own settings and plays its inputs back into it.
+    fn watch_replay(&mut self) {
+        let Some(replay) = self.replay else {
+            self.replay_message = "No replay to watch";
+            return;
+        };
+        let settings = self.settings;
+        self.settings.seed = replay.seed;
+        self.settings.level = replay.level;
+        self.settings.lives = replay.lives;
+        self.settings.mode = replay.mode;
+        self.enter(Status::Normal);
+        self.settings = settings;
+        self.playback = Some(Playback::new());
+    }
+
+    // Feeds in every recorded input that is due by now.
+    fn play_back(&mut self) {
+        while let (Some(mut playback), Status::Normal | Status::LevelComplete) = (self.playback, self.status) {
+            let Some((player, action)) = self.replay.as_ref().and_then(|r| r.next(&mut playback, self.tick_count)) else {
+                return;
+            };
+            self.playback = Some(playback);
+            match (action, self.status) {
+                (Action::Confirm, Status::LevelComplete) => self.next_level(),
+                (Action::Confirm, _) => {}
+                (action, _) => self.player_action(player, action),
             }
         }
-        if self.proj_count < 50 {
-            for i in 0..self.proj_count {
-                if self.projectiles[i as usize].x < 79 && self.projectiles[i as usize].y < 24 {
-                    self.projectiles[i as usize].draw();
-                }
-                if self.player.proj_collision(&self.projectiles[i as usize]) {
-                    self.status = Status::Over;
-                }
-                
+    }
+
+    fn imported(&mut self, result: Result<Replay, ReplayError>) {
+        self.import = None;
+        match result {
+            Ok(replay) => {
+                self.replay = Some(replay);
+                self.replay_message = "Replay received";
             }
-        } else {
-            for i in self.proj_count-self.drawn_proj..self.proj_count {
-                if self.projectiles[i as usize].x < 79 && self.projectiles[i as usize].y < 24 {
-                    self.projectiles[i as usize].draw();
-                }
-                if self.player.proj_collision(&self.projectiles[i as usize]) {
-                    self.status = Status::Over;
-                }
+            Err(e) => {
+                serial_println!("Replay rejected: {}", e);
+                self.replay_message = e.message();
             }
         }
-        plot_num(self.tick_count, 7, 0, ColorCode::new(Color::White, Color::Black));
-        plot_str("Score:", 1, 0, ColorCode::new(Color::White, Color::Black));
-        match self.status {
-            Status::Normal => {},
-            Status::Over => {
-                self.tick_count -= 1;
-                plot_str("Game Over! Press 's' to restart", BUFFER_HEIGHT / 2, 0, ColorCode::new(Color::White, Color::Black));
+    }
+
+    fn settings_action(&mut self, action: Action) {
+        let step = match action {
+            Action::MoveLeft => -1,
+            Action::MoveRight => 1,
+            _ => 0,
+        };
+        match (self.settings_menu.selected(), step) {
+            (0, step) if step != 0 => {
+                self.settings.lives = (self.settings.lives + step).clamp(1, 9);
+                self.lives = [self.settings.lives; 2];
+            }
+            (1, step) if step != 0 => {
+                self.settings.level = (self.settings.level as isize + step).rem_euclid(LEVELS.len() as isize) as usize;
+                self.load_level(self.settings.level);
+            }
+            (2, step) if step != 0 => self.set_mode(self.settings.mode.step(step)),
+            (3, step) if step != 0 => self.settings.reroll = !self.settings.reroll,
+            _ => match self.settings_menu.action(action) {
+                Some(4) => self.open_controls(),
+                Some(5) => self.status = Status::Title,
+                _ if action == Action::Back => self.status = Status::Title,
+                _ => {}
             },
         }
     }
-}
 
-#[derive(Copy, Clone)]
-pub struct Walls {
-    walls: [[bool; BUFFER_WIDTH]; BUFFER_HEIGHT]
-}
-
-impl Walls {
-    pub fn new(map: &str) -> Self {
-        let mut walls = [[false; BUFFER_WIDTH]; BUFFER_HEIGHT];
-        for (row, chars) in map.split('\n').enumerate() {
-            for (col, value) in chars.char_indices() {
-                walls[row][col] = value == '#';
-            }
+    // Starts a new run. With rerolling on, a seed that has already been played is swapped for
+    // a new one first; otherwise the run replays the last one exactly.
+    fn start_run(&mut self) {
+        if self.settings.reroll && self.settings.last_seed == Some(self.settings.seed) {
+            self.settings.seed = seed::mix(self.settings.seed);
         }
-        Self {walls}
+        self.settings.last_seed = Some(self.settings.seed);
+        self.enter(Status::Normal);
+        self.start_recording();
     }
 
-    pub fn draw(&self) {
-        for row in 0..self.walls.len() {
-            for col in 0..self.walls[row].len() {
-                plot(self.char_at(row, col), col, row, ColorCode::new(Color::Blue, Color::Black));
-            }
+    fn start_recording(&mut self) {
+        self.replay = Some(Replay::new(self.seed, self.level, self.settings.lives, self.mode));
+        self.recording = true;
+    }
+
+    fn next_level(&mut self) {
+        self.load_level(self.level + 1);
+        self.status = Status::Normal;
+    }
+
+    // Throws the current run away and moves to `status` with a new one. Settings, high scores,
+    // the level in the editor, the link and the last replay carry over, along with a replay
+    // still being sent so that starting a run doesn't cut it off.
+    fn enter(&mut self, status: Status) {
+        let (scores_changed, net, replay) = (self.scores_changed, self.net, self.replay);
+        let (export, replay_message) = (self.export, self.replay_message);
+        *self = Self::fresh(self.settings, self.editor, self.scores);
+        self.scores_changed = scores_changed;
+        self.net = net;
+        self.replay = replay;
+        if export.is_some() {
+            (self.export, self.replay_message) = (export, replay_message);
         }
+        self.status = status;
     }
 
-    pub fn occupied(&self, row: usize, col: usize) -> bool {
-        if row > 24 || col > 79 {
-            return true;
+    // Edits the level being played, or goes back to the copy already being play-tested.
+    fn open_editor(&mut self) {
+        if !self.play_testing {
+            self.editor = Editor::new(self.level_info);
         }
-        self.walls[row][col]
+        self.status = Status::Editor;
     }
 
-    fn char_at(&self, row: usize, col: usize) -> char {
-        if self.walls[row][col] {
-            '#'
-        } else {
-            ' '
+    fn open_controls(&mut self) {
+        if self.status == Status::Normal {
+            self.status = Status::Paused;
         }
+        self.menu_return = self.status;
+        self.controls = ControlsMenu::new();
+        self.status = Status::Controls;
     }
-}
 
-#[derive(Copy, Clone)]
-pub struct Projectile {
-    x: usize,
-    y: usize,
-    dir: usize, 
-}
+    // Starts looking for another kernel on the serial link, offering this one's settings.
+    fn connect(&mut self) {
+        self.net.connect(self.seed, self.settings.level as u8, self.settings.lives as u8);
+        self.status = Status::Connecting;
+    }
 
-impl Projectile {
-    pub fn new() -> Self {
-        Self {x: 100 , y: 100, dir: 0}
+    // Tells the other side we've gone, if it's still there, and goes back to the title.
+    fn leave_match(&mut self) {
+        self.net.leave();
+        self.enter(Status::Title);
     }
 
-    pub fn draw(&self) {
-        plot('X', self.x, self.y, ColorCode::new(Color::Yellow, Color::Black));
+    /// Exchanges bytes with the other kernel in a linked game: handles whatever has arrived
+    /// and sends whatever is waiting to go. Call it as often as possible.
+    pub fn link<L: SerialLink>(&mut self, link: &mut L) {
+        while let Some(byte) = link.receive() {
+            if let Some(import) = self.import.as_mut() {
+                if let Some(result) = import.push(byte) {
+                    self.imported(result);
+                }
+            } else if let Some(event) = self.net.receive(byte) {
+                self.link_event(event);
+            }
+        }
+        if let Some(export) = self.export.as_mut() {
+            if export.send(link, REPLAY_CHUNK) {
+                self.export = None;
+                self.replay_message = "Replay sent";
+            }
+        }
+        self.net.flush(link);
     }
 
-    pub fn move_to(&mut self, x: usize, y: usize) {
-        self.x = x;
-        self.y = y;
+    pub fn link_state(&self) -> LinkState {
+        self.net.state()
     }
 
-    pub fn change_dir(&mut self, dir: usize) {
-        self.dir = dir;
+    fn link_event(&mut self, event: LinkEvent) {
+        match event {
+            LinkEvent::Start(host) => self.start_match(host),
+            LinkEvent::Ended(end) => {
+                if let LinkEnd::Desync(frame) = end {
+                    serial_println!("Linked game out of sync at frame {}", frame);
+                }
+                if matches!(self.status, Status::Normal | Status::Connecting) {
+                    self.status = Status::Over;
+                }
+            }
+        }
     }
 
-    pub fn momentum(&mut self) {
-        if self.dir == 0 {
-            self.x += 1;
-        } else if self.dir == 1 {
-            self.y += 1;
-        } else if self.dir == 2 {
-            self.x -= 1;
-        } else {
-            self.y -= 1;
+    // Both sides start the same run from the same seed, so from here on only inputs need
+    // to cross the link.
+    fn start_match(&mut self, host: Hello) {
+        self.enter(Status::Normal);
+        self.mode = PlayMode::Linked;
+        self.lives = [host.lives as isize; 2];
+        self.seed = host.seed;
+        self.rng = SmallRng::seed_from_u64(host.seed as u64);
+        self.load_level(host.level as usize);
+    }
+
+    // Runs the next frame of a linked game once both players' inputs for it are in.
+    // Until then the game holds still.
+    fn linked_update(&mut self) {
+        let Some(inputs) = self.net.inputs() else {
+            self.net.tick();
+            return;
+        };
+        for (player, inputs) in inputs.iter().enumerate() {
+            for action in inputs.actions() {
+                self.player_action(player, action);
+            }
+        }
+        self.update();
+        let frame = self.net.frame();
+        if frame.is_multiple_of(HASH_INTERVAL) {
+            if let Some(event) = self.net.record_hash(frame, self.state_hash()) {
+                self.link_event(event);
+            }
         }
+        self.net.advance();
     }
 
-    pub fn occupied(&self, row: usize, col: usize) -> bool {
-        if row > 24 || col > 79 {
-            return true;
-        } else if self.x == col && self.y == row {
-            return true;
-        } else {
-            return false;
+    /// An FNV-1a hash of everything that decides how the game plays out. Two linked games
+    /// that agree on it are still in step.
+    pub fn state_hash(&self) -> u32 {
+        let mut hash = 0x811C_9DC5u32;
+        let mut add = |value: usize| {
+            for byte in value.to_le_bytes() {
+                hash = (hash ^ byte as u32).wrapping_mul(0x0100_0193);
+            }
+        };
+        add(self.tick_count as usize);
+        add(self.wave as usize);
+        add(self.level);
+        for i in 0..self.players.len() {
+            add(self.score[i] as usize);
+            add(self.lives[i] as usize);
+            add(self.players[i].x);
+            add(self.players[i].y);
         }
+        for shootr in self.shooters.iter() {
+            add(shootr.x);
+            add(shootr.y);
+        }
+        for proj in self.projectiles.iter().chain(self.player_projectiles.iter().flatten()).filter(|p| p.is_live()) {
+            add(proj.x);
+            add(proj.y);
+        }
+        add(self.rng.clone().next_u32() as usize);
+        hash
     }
 
-    pub fn remove(&mut self) {
-        self.x = 100;
-        self.y = 100;
+    // Switches to one of the built-in levels, keeping score, lives and wave. A level that
+    // fails to parse is reported and replaced by the plain arena so the game can go on.
+    pub fn load_level(&mut self, index: usize) {
+        self.level = index % LEVELS.len();
+        self.level_info = match Level::parse(LEVELS[self.level]) {
+            Ok(level) => {
+                self.level_error = None;
+                level
+            }
+            Err(e) => {
+                serial_println!("Level {} failed to load: {}", self.level + 1, e);
+                self.level_error = Some(e);
+                Level::fallback()
+            }
+        };
+        self.start_level();
     }
-}
 
-#[derive(Copy, Clone)]
-pub struct Shooter {
-    x: usize, 
-    y: usize,
-}
+    // Starts a fresh game on the level being edited. When the run ends or the level is
+    // cleared, 'e' (or clearing it) goes straight back to the editor.
+    fn play_test(&mut self) {
+        self.enter(Status::Normal);
+        self.level_error = None;
+        self.level_info = *self.editor.level();
+        self.start_level();
+        self.play_testing = true;
+    }
 
-impl Shooter {
-    pub fn new() -> Self {
-        Self {x: 100, y : 100}
+    // Sets up `level_info` for play.
+    fn start_level(&mut self) {
+        self.walls = self.level_info.walls;
+        self.grid.clear_walls();
+        for y in 0..BUFFER_HEIGHT {
+            for x in (0..BUFFER_WIDTH).filter(|x| self.walls.occupied(y, *x)) {
+                self.grid.add_wall(x, y);
+            }
+        }
+        for i in 0..self.players.len() {
+            let (x, y) = self.spawn_point(i);
+            self.players[i] = Player::new();
+            self.players[i].move_to(x, y);
+        }
+        self.shooters.clear();
+        self.projectiles.clear();
+        self.player_projectiles = [[Projectile::new(); 8]; 2];
+        self.power_ups = [PowerUp::new(); 4];
+        self.junk = [Junk::new(); 8];
+        for (i, (x, y, kind)) in self.level_info.pickups().iter().enumerate().take(self.power_ups.len()) {
+            self.power_ups[i].place(*x, *y, *kind);
+        }
+        self.level_start = self.tick_count;
+        self.level_first_wave = self.wave;
+        self.banner = WAVE_BANNER_TICKS;
+        self.map();
     }
 
-    pub fn move_to(&mut self, x: usize, y: usize) {
-        self.x = x;
-        self.y = y;
+    // Rebuilds the occupancy grid from scratch. This happens once a tick, after everything
+    // has moved; between rebuilds the grid is kept up to date as things spawn, move and go.
+    fn map(&mut self) {
+        self.grid.clear();
+        for i in 0..self.shooters.len() {
+            let slot = self.shooters.slot(i);
+            let shootr = self.shooters.get(slot);
+            self.grid.add_shooter(shootr.x, shootr.y, slot);
+        }
+        for i in 0..self.projectiles.len() {
+            let slot = self.projectiles.slot(i);
+            let proj = self.projectiles.get(slot);
+            self.grid.add_projectile(proj.x, proj.y, slot);
+        }
+        for (i, junk) in self.junk.iter().enumerate().filter(|(_, j)| j.is_live()) {
+            self.grid.add_pickup(junk.x, junk.y, Pickup::Junk(i));
+        }
+        for (i, power_up) in self.power_ups.iter().enumerate().filter(|(_, p)| p.is_live()) {
+            self.grid.add_pickup(power_up.x, power_up.y, Pickup::PowerUp(i));
+        }
     }
 
-    pub fn shoot_right(&self) -> Projectile {
-        let mut proj = Projectile::new();
-        proj.move_to(self.x + 1, self.y);
-        proj.change_dir(0);
-        proj
+    // Where a player appears: the level start for the first player, beside it for the second.
+    fn spawn_point(&self, player: usize) -> (usize, usize) {
+        let (x, y) = self.level_info.start;
+        if player == 0 {
+            return (x, y);
+        }
+        [(x + 2, y), (x.wrapping_sub(2), y), (x, y + 2), (x, y.wrapping_sub(2))].into_iter()
+            .find(|(x, y)| !self.grid.wall(*x, *y))
+            .unwrap_or((x, y))
     }
-    pub fn shoot_down(&self) -> Projectile {
-        let mut proj = Projectile::new();
-        proj.move_to(self.x, self.y + 1);
-        proj.change_dir(1);
-        proj
+
+    fn in_play(&self, player: usize) -> bool {
+        player < self.mode.players() && self.lives[player] > 0
     }
-    pub fn shoot_left(&self) -> Projectile {
-        let mut proj = Projectile::new();
-        proj.move_to(self.x - 1, self.y);
-        proj.change_dir(2);
-        proj
+
+    // Points for something one player did. Co-op players share the first score.
+    fn award(&mut self, player: usize, points: isize) {
+        let slot = if self.mode.rivals() {player} else {0};
+        self.score[slot] += points;
     }
-    pub fn shoot_up(&self) -> Projectile {
-        let mut proj = Projectile::new();
-        proj.move_to(self.x, self.y - 1);
-        proj.change_dir(3);
-        proj
+
+    // Points for everyone still in the game, like the wave and time bonuses.
+    fn award_all(&mut self, points: isize) {
+        if self.mode.rivals() {
+            for i in 0..self.players.len() {
+                if self.in_play(i) {
+                    self.score[i] += points;
+                }
+            }
+        } else {
+            self.score[0] += points;
+        }
     }
 
-    pub fn draw(&self) {
-        plot('S', self.x, self.y, ColorCode::new(Color::Magenta, Color::Black));
+    // The nearest player still in the game to the given cell, for shooters to chase and aim at.
+    fn target(&self, x: usize, y: usize) -> (usize, usize) {
+        (0..self.players.len()).filter(|i| self.in_play(*i))
+            .map(|i| (self.players[i].x, self.players[i].y))
+            .min_by_key(|(px, py)| px.abs_diff(x) + py.abs_diff(y))
+            .unwrap_or((self.players[0].x, self.players[0].y))
     }
 
-    pub fn shift(&mut self, rng: &mut SmallRng, walls: Walls) {
-        let dir = rng.next_u32() % 4;
-        if dir == 0 && !walls.occupied(self.x+1, self.y){
-            self.x += 1;
-        } else if dir == 1 && !walls.occupied(self.x, self.y+1){
-            self.y += 1;
-        } else if dir == 2 && !walls.occupied(self.x-1, self.y){
-            self.x -= 1;
-        } else if !walls.occupied(self.x, self.y-1){
-            self.y -= 1;
+    fn complete_level(&mut self) {
+        if self.play_testing {
+            self.status = Status::Editor;
+            return;
+        }
+        self.level_seconds = (self.tick_count - self.level_start) / TICKS_PER_SECOND;
+        self.level_bonus = if self.level_seconds <= self.level_info.par {PAR_BONUS} else {0};
+        self.award_all(self.level_bonus);
+        if self.mode == PlayMode::Linked {
+            // Stopping for the summary would need both players to agree to go on.
+            self.load_level(self.level + 1);
+        } else {
+            self.status = Status::LevelComplete;
         }
     }
-}
 
-#[derive(Copy, Clone)]
-pub struct Player {
-    x: usize,
-    y: usize,
-}
+    // Spawns the wave's shooters at its spawn rate, and moves on to the next wave
+    // once the time is up or every shooter in it has been destroyed.
+    fn update_wave(&mut self) {
+        if self.banner > 0 {
+            self.banner -= 1;
+            if self.banner == 0 {
+                self.wave_start = self.tick_count;
+                self.wave_spawned = 0;
+            }
+            return;
+        }
+        let wave = Wave::number(self.wave);
+        let elapsed = self.tick_count - self.wave_start;
+        if self.wave_spawned < wave.shooters && elapsed % wave.spawn_freq == 0 {
+            self.spawn_shooter(&wave);
+        }
+        let cleared = self.wave_spawned == wave.shooters && self.shooters.is_empty();
+        if elapsed >= wave.duration || cleared {
+            self.award_all(WAVE_BONUS * self.wave);
+            self.wave += 1;
+            self.banner = WAVE_BANNER_TICKS;
+            if self.wave - self.level_first_wave >= self.level_info.waves {
+                self.complete_level();
+            }
+        }
+    }
 
-impl Player {
-    pub fn new() -> Self {
-        Self {x: BUFFER_WIDTH / 2, y: BUFFER_HEIGHT / 2}
+    fn spawn_shooter(&mut self, wave: &Wave) {
+        let (nx, ny) = if self.level_info.spawn_count > 0 {
+            self.level_info.spawns()[self.rng.next_u32() as usize % self.level_info.spawn_count]
+        } else {
+            (1 + self.rng.next_u32() as usize % (BUFFER_WIDTH - 2), 3)
+        };
+        if !self.grid.wall(nx, ny) {
+            let kind = self.random_shooter_kind(&SHOOTER_WEIGHTS[..wave.kinds]);
+            let movement = MOVEMENTS[self.rng.next_u32() as usize % MOVEMENTS.len()];
+            let mut shootr = Shooter::of_kind(kind);
+            shootr.set_movement(movement);
+            shootr.move_to(nx, ny);
+            // With the arena full the wave holds this one back until a shooter is destroyed.
+            if let Some(slot) = self.shooters.spawn(shootr) {
+                self.grid.add_shooter(nx, ny, slot);
+                self.wave_spawned += 1;
+            }
+        }
     }
 
-    pub fn is_colliding(&self, walls: &Walls) -> bool {
-        walls.occupied(self.y, self.x)
+    fn power_active(&self, kind: PowerKind) -> bool {
+        self.active_power == Some(kind)
     }
 
-    pub fn proj_collision(&self, proj: &Projectile) -> bool {
-        proj.occupied(self.y, self.x)
+    // Drops a random power-up on an empty cell, if there is room for one.
+    fn spawn_power_up(&mut self) {
+        if let Some(slot) = self.power_ups.iter().position(|p| !p.is_live()) {
+            let x = self.rng.next_u32() as usize % BUFFER_WIDTH;
+            let y = 1 + self.rng.next_u32() as usize % (BUFFER_HEIGHT - 1);
+            let kind = match self.rng.next_u32() % 5 {
+                0 | 1 => PowerKind::Shield,
+                2 | 3 => PowerKind::SlowTime,
+                _ => PowerKind::Bomb,
+            };
+            if !self.grid.wall(x, y) {
+                self.power_ups[slot].place(x, y, kind);
+                self.grid.add_pickup(x, y, Pickup::PowerUp(slot));
+            }
+        }
     }
 
-    pub fn down(&mut self) {
-        self.y += 1;
+    fn spawn_junk(&mut self) {
+        if let Some(slot) = self.junk.iter().position(|j| !j.is_live()) {
+            let x = self.rng.next_u32() as usize % BUFFER_WIDTH;
+            let y = 1 + self.rng.next_u32() as usize % (BUFFER_HEIGHT - 1);
+            let dir = 4 + self.rng.next_u32() as usize % 4;
+            let value = JUNK_VALUES[self.rng.next_u32() as usize % JUNK_VALUES.len()];
+            if self.walls.tile(y, x) == Tile::Floor {
+                self.junk[slot].place(x, y, dir, value);
+                self.grid.add_pickup(x, y, Pickup::Junk(slot));
+            }
+        }
     }
 
-    pub fn up(&mut self) {
-        self.y -= 1;
+    // Picking junk up soon after the last piece builds a combo that multiplies its value.
+    fn collect_junk(&mut self) {
+        for player in 0..self.players.len() {
+            let (x, y) = (self.players[player].x, self.players[player].y);
+            if let (true, Some(Pickup::Junk(i))) = (self.in_play(player), self.grid.pickup(x, y)) {
+                let junk = self.junk[i];
+                self.junk[i].remove();
+                self.grid.remove_pickup(x, y);
+                if self.junk_collected > 0 && self.tick_count - self.last_pickup <= COMBO_WINDOW {
+                    self.combo = (self.combo + 1).min(MAX_COMBO);
+                } else {
+                    self.combo = 1;
+                }
+                self.last_pickup = self.tick_count;
+                self.junk_collected += 1;
+                self.award(player, junk.value * self.combo);
+            }
+        }
     }
 
-    pub fn left(&mut self) {
-        self.x -= 1;
+    fn collect_power_ups(&mut self) {
+        for player in 0..self.players.len() {
+            let (x, y) = (self.players[player].x, self.players[player].y);
+            if let (true, Some(Pickup::PowerUp(i))) = (self.in_play(player), self.grid.pickup(x, y)) {
+                let power_up = self.power_ups[i];
+                self.power_ups[i].remove();
+                self.grid.remove_pickup(x, y);
+                // A bomb goes off at once, so it leaves a running shield or slow-time alone.
+                if power_up.kind == PowerKind::Bomb {
+                    self.projectiles.clear();
+                    self.grid.clear_projectiles();
+                } else {
+                    self.active_power = Some(power_up.kind);
+                    self.power_until = self.tick_count + power_up.kind.duration();
+                }
+            }
+        }
     }
 
-    pub fn right(&mut self) {
-        self.x += 1;
+    // Loses a life and respawns the player at the start, briefly invulnerable. A player with
+    // no lives left sits out, and the game is over once nobody is left.
+    fn player_hit(&mut self, player: usize) {
+        self.lives[player] -= 1;
+        if self.lives[player] > 0 {
+            let (x, y) = self.spawn_point(player);
+            self.players[player] = Player::new();
+            self.players[player].move_to(x, y);
+            self.players[player].invulnerable = INVULNERABLE_TICKS;
+        } else if !(0..self.players.len()).any(|p| self.in_play(p)) {
+            // A watched replay already had its chance at the table.
+            let scored = self.play_testing || self.playback.is_some() || self.mode == PlayMode::Linked;
+            if !scored && self.scores.qualifies(self.score()) {
+                self.initials = InitialsEntry::new();
+                self.status = Status::EnterInitials;
+            } else {
+                self.status = Status::Over;
+            }
+        }
     }
-}
\ No newline at end of file
+
+    // Steps the player, then carries them through a teleporter and keeps them sliding
+    // while they are on ice. The player still turns to face a blocked direction.
+    fn move_player(&mut self, player: usize, dir: usize) {
+        let mut future = self.players[player];
+        future.step(dir);
+        self.players[player].dir = dir;
+        if self.walls.blocks(future.y, future.x, dir) {
+            return;
+        }
+        for _ in 0..BUFFER_WIDTH {
+            if let Some((x, y)) = self.walls.teleport(future.y, future.x) {
+                future.move_to(x, y);
+            }
+            if self.walls.tile(future.y, future.x) != Tile::Ice {
+                break;
+            }
+            let mut slide = future;
+            slide.step(dir);
+            if self.walls.blocks(slide.y, slide.x, dir) {
+                break;
+            }
+            future = slide;
+        }
+        self.players[player] = future;
+    }
+
+    // Only a handful of player shots can be in the air at once; firing with none free does nothing.
+    pub fn fire(&mut self, player: usize) {
+        let shot = self.players[player].shoot();
+        if self.walls.blocks(shot.y, shot.x, shot.dir) {
+            self.walls.hit(shot.y, shot.x);
+            return;
+        }
+        if let Some(slot) = self.player_projectiles[player].iter_mut().find(|p| !p.is_live()) {
+            *slot = shot;
+            self.player_shot_hits();
+        }
+    }
+
+    fn player_shot_hits(&mut self) {
+        for player in 0..self.players.len() {
+            for p in 0..self.player_projectiles[player].len() {
+                let proj = self.player_projectiles[player][p];
+                if !proj.is_live() {
+                    continue;
+                }
+                if let Some(slot) = self.grid.shooter(proj.x, proj.y) {
+                    let points = self.shooters.get(slot).kind.points();
+                    self.shooters.despawn(slot);
+                    self.grid.remove_shooter(proj.x, proj.y, slot);
+                    self.player_projectiles[player][p].remove();
+                    self.award(player, points);
+                }
+            }
+        }
+    }
+
+    fn random_shooter_kind(&mut self, weights: &[(ShooterKind, u32)]) -> ShooterKind {
+        let total: u32 = weights.iter().map(|(_, w)| w).sum();
+        let mut pick = self.rng.next_u32() % total;
+        for (kind, weight) in weights {
+            if pick < *weight {
+                return *kind;
+            }
+            pick -= weight;
+        }
+        ShooterKind::Basic
+    }
+
+    // Adds an enemy projectile, unless it would start out inside a wall. With the pool full
+    // the shot just isn't fired.
+    fn launch(&mut self, proj: Projectile) {
+        if !self.walls.blocks(proj.y, proj.x, proj.dir) {
+            self.projectiles.spawn(proj);
+        }
+    }
+
+    /// How many enemy projectiles are in flight, and how close the pool has come to running out.
+    pub fn projectile_stats(&self) -> PoolStats {
+        self.projectiles.stats()
+    }
+
+    pub fn shooter_stats(&self) -> PoolStats {
+        self.shooters.stats()
+    }
+
+    fn draw_results<C: Canvas>(&self, canvas: &mut C) {
+        let color = ColorCode::new(Color::White, Color::Blue);
+        let col = BUFFER_WIDTH / 2 - 14;
+        let row = BUFFER_HEIGHT / 2 - 1;
+        let seconds = ("Seconds alive:  ", self.tick_count / TICKS_PER_SECOND);
+        let junk = ("Junk collected: ", self.junk_collected);
+        let seed = ("Seed:           ", self.seed as isize);
+        let rivals = [("Player 1 score: ", self.score[0]), ("Player 2 score: ", self.score[1]), seconds, junk, seed];
+        let shared = [("Final score:    ", self.score[0]), seconds, junk, seed];
+        let rows: &[(&str, isize)] = if self.mode.rivals() {&rivals} else {&shared};
+        for (i, (label, value)) in rows.iter().enumerate() {
+            canvas.plot_str("                            ", col, row + i, color);
+            let end = canvas.plot_str(label, col + 1, row + i, color);
+            canvas.plot_num(*value, end, row + i, color);
+        }
+    }
+
+    // Blanks the two lines of game-over instructions under the results panel, well clear of
+    // the HUD, and returns where they start.
+    fn clear_over_lines<C: Canvas>(&self, canvas: &mut C) -> (usize, usize) {
+        let (col, row) = (BUFFER_WIDTH / 2 - 32, BUFFER_HEIGHT / 2 + 5);
+        for r in row..row + 2 {
+            for c in col..col + 64 {
+                canvas.plot(' ', c, r, ColorCode::new(Color::White, Color::Black));
+            }
+        }
+        (col, row)
+    }
+
+    pub fn tick<C: Canvas>(&mut self, canvas: &mut C) {
+        match self.status {
+            Status::Title => self.draw_title(canvas),
+            Status::HighScores => self.draw_high_scores(canvas),
+            Status::Settings => self.draw_settings(canvas),
+            Status::Editor => self.editor.draw(canvas),
+            Status::Controls => self.controls.draw(canvas, &self.settings.bindings),
+            Status::Connecting => {
+                self.net.tick();
+                self.draw_connecting(canvas);
+            }
+            Status::SeedEntry => self.draw_seed_entry(canvas),
+            Status::Replays => self.draw_replays(canvas),
+            Status::Normal if self.mode == PlayMode::Linked => {
+                self.linked_update();
+                self.draw(canvas);
+            }
+            Status::Normal | Status::Paused | Status::LevelComplete | Status::EnterInitials | Status::Over => {
+                self.play_back();
+                self.update();
+                self.draw(canvas);
+            }
+        }
+    }
+
+    // Advances the world by one tick. The world only moves during play, so pausing,
+    // finishing a level or losing freezes everything where it was.
+    fn update(&mut self) {
+        if self.status != Status::Normal {
+            return;
+        }
+        self.update_wave();
+        if self.tick_count % ADD_POWER_UP_FREQ == ADD_POWER_UP_FREQ - 1 {
+            self.spawn_power_up();
+        }
+        if self.tick_count % ADD_JUNK_FREQ == ADD_JUNK_FREQ - 1 {
+            self.spawn_junk();
+        }
+        self.tick_count += 1;
+        if self.tick_count % TICKS_PER_SECOND == 0 {
+            self.award_all(SURVIVAL_POINTS);
+        }
+        if self.combo > 0 && self.tick_count - self.last_pickup > COMBO_WINDOW {
+            self.combo = 0;
+        }
+        if self.tick_count % JUNK_DRIFT_FREQ == 0 {
+            for i in 0..self.junk.len() {
+                let junk = self.junk[i];
+                if junk.is_live() {
+                    self.grid.remove_pickup(junk.x, junk.y);
+                    self.junk[i].drift(&self.walls);
+                    self.grid.add_pickup(self.junk[i].x, self.junk[i].y, Pickup::Junk(i));
+                }
+            }
+        }
+        self.collect_junk();
+        if self.active_power.is_some() && self.tick_count >= self.power_until {
+            self.active_power = None;
+        }
+        self.collect_power_ups();
+        let slow = if self.power_active(PowerKind::SlowTime) {2} else {1};
+        if self.tick_count % (Wave::number(self.wave).move_freq * slow) == 0 {
+            for i in 0..self.shooters.len() {
+                let slot = self.shooters.slot(i);
+                let shootr = *self.shooters.get(slot);
+                let (x, y) = self.target(shootr.x, shootr.y);
+                self.shooters.get_mut(slot).shift(&mut self.rng, &self.walls, x, y);
+                self.grid.remove_shooter(shootr.x, shootr.y, slot);
+                let shootr = self.shooters.get(slot);
+                self.grid.add_shooter(shootr.x, shootr.y, slot);
+            }
+            self.shooters.sweep();
+        }
+        self.player_shot_hits();
+        for proj in self.player_projectiles.iter_mut().flatten() {
+            if proj.is_live() && !(self.walls.tile(proj.y, proj.x) == Tile::Slow && self.tick_count % 2 == 1) {
+                self.walls.advance(proj);
+            }
+        }
+        self.player_shot_hits();
+        if self.tick_count % (MOVE_PROJ_FREQ * slow) == 0 {
+            let crawl = (self.tick_count / (MOVE_PROJ_FREQ * slow)) % 2 == 1;
+            self.projectiles.retain_mut(|proj| {
+                if !(crawl && self.walls.tile(proj.y, proj.x) == Tile::Slow) {
+                    self.walls.advance(proj);
+                }
+            });
+        }
+        if self.tick_count % slow == 0 {
+            let clock = self.tick_count / slow;
+            for i in 0..self.shooters.len() {
+                let slot = self.shooters.slot(i);
+                let (x, y) = self.target(self.shooters.get(slot).x, self.shooters.get(slot).y);
+                let shots = self.shooters.get_mut(slot).fire(clock, x, y);
+                for proj in shots.iter().flatten() {
+                    self.launch(*proj);
+                }
+            }
+        }
+        self.map();
+        self.projectile_hits();
+    }
+
+    // Runs once the grid is up to date: a projectile on a player takes a life, unless a
+    // shield takes the hit instead.
+    fn projectile_hits(&mut self) {
+        for i in 0..self.players.len() {
+            if !self.in_play(i) {
+                continue;
+            }
+            let player = self.players[i];
+            if player.invulnerable > 0 {
+                self.players[i].invulnerable -= 1;
+            } else if self.grid.projectiles(player.x, player.y) > 0 {
+                if self.power_active(PowerKind::Shield) {
+                    self.active_power = None;
+                    // The shield soaks up everything in the cell, which the grid names by slot.
+                    for slot in self.grid.projectile_slots(player.x, player.y) {
+                        self.projectiles.despawn(slot);
+                    }
+                    self.grid.remove_projectiles(player.x, player.y);
+                } else {
+                    self.player_hit(i);
+                }
+            }
+        }
+    }
+
+    fn draw<C: Canvas>(&self, canvas: &mut C) {
+        self.walls.draw(canvas);
+        for power_up in self.power_ups.iter() {
+            if power_up.is_live() {
+                power_up.draw(canvas);
+            }
+        }
+        for junk in self.junk.iter() {
+            if junk.is_live() {
+                junk.draw(canvas);
+            }
+        }
+        for (i, player) in self.players.iter().enumerate() {
+            if self.in_play(i) && player.visible() {
+                let (glyph, color) = PLAYER_LOOKS[i];
+                canvas.plot(glyph, player.x, player.y, ColorCode::new(color, Color::Black));
+            }
+        }
+        for shootr in self.shooters.iter() {
+            shootr.draw(canvas);
+        }
+        for proj in self.projectiles.iter() {
+            proj.draw(canvas);
+        }
+        for proj in self.player_projectiles.iter().flatten() {
+            if proj.is_live() {
+                proj.draw_friendly(canvas);
+            }
+        }
+        // The first player's score and lives go on the top row. With two players the second
+        // player's go on the bottom row, in their color; co-op players share the top score.
+        let players = self.mode.players();
+        for i in 0..players {
+            let (row, color) = match (i, players) {
+                (0, 1) => (0, Color::White),
+                (0, _) => (0, PLAYER_LOOKS[0].1),
+                _ => (BUFFER_HEIGHT - 1, PLAYER_LOOKS[1].1),
+            };
+            let color = ColorCode::new(color, Color::Black);
+            if i == 0 || self.mode.rivals() {
+                canvas.plot_str("Score:", 1, row, color);
+                canvas.plot_num(self.score[i], 7, row, color);
+            }
+            canvas.plot_str("Lives:", 20, row, color);
+            canvas.plot_num(self.lives[i].max(0), 27, row, color);
+            if self.mode == PlayMode::Linked && i == self.net.local_player() {
+                canvas.plot_str("(you)", 29, row, color);
+            }
+        }
+        // The second player's HUD ends by column 34, so the replay tag and the seed fit after it at
+        // the right end of the bottom row.
+        let col = canvas.plot_str("Seed ", 64, BUFFER_HEIGHT - 1, ColorCode::new(Color::DarkGray, Color::Black));
+        canvas.plot_num(self.seed as isize, col, BUFFER_HEIGHT - 1, ColorCode::new(Color::DarkGray, Color::Black));
+        if self.playback.is_some() {
+            canvas.plot_str("REPLAY", 56, BUFFER_HEIGHT - 1, ColorCode::new(Color::Black, Color::Yellow));
+        }
+        canvas.plot_str("Wave:", 60, 0, ColorCode::new(Color::White, Color::Black));
+        canvas.plot_num(self.wave, 66, 0, ColorCode::new(Color::White, Color::Black));
+        if self.banner > 0 {
+            let col = canvas.plot_str("Wave ", BUFFER_WIDTH / 2 - 3, BUFFER_HEIGHT / 2 - 2, ColorCode::new(Color::Yellow, Color::Black));
+            canvas.plot_num(self.wave, col, BUFFER_HEIGHT / 2 - 2, ColorCode::new(Color::Yellow, Color::Black));
+            let name = self.level_info.name;
+            canvas.plot_str(name, (BUFFER_WIDTH - name.len().min(BUFFER_WIDTH)) / 2, BUFFER_HEIGHT / 2 - 1, ColorCode::new(Color::Yellow, Color::Black));
+            // A level that failed to load says why under the fallback's name, off the HUD rows.
+            if let Some(e) = self.level_error {
+                let row = BUFFER_HEIGHT / 2 + 1;
+                let width = "Level error: ".len() + e.message().len() + if e.line().is_some() {" on line 99".len()} else {0};
+                let col = canvas.plot_str("Level error: ", (BUFFER_WIDTH - width) / 2, row, ColorCode::new(Color::White, Color::Red));
+                let col = canvas.plot_str(e.message(), col, row, ColorCode::new(Color::White, Color::Red));
+                if let Some(line) = e.line() {
+                    let col = canvas.plot_str(" on line ", col, row, ColorCode::new(Color::White, Color::Red));
+                    canvas.plot_num(line as isize, col, row, ColorCode::new(Color::White, Color::Red));
+                }
+            }
+        }
+        if let Some(kind) = self.active_power {
+            let col = canvas.plot_str(kind.label(), 33, 0, kind.color());
+            canvas.plot_num(self.power_until - self.tick_count, col + 1, 0, kind.color());
+        }
+        if self.combo > 1 {
+            let col = canvas.plot_str("Combo x", 48, 0, ColorCode::new(Color::Yellow, Color::Black));
+            canvas.plot_num(self.combo, col, 0, ColorCode::new(Color::Yellow, Color::Black));
+        }
+        match self.status {
+            Status::Paused => {
+                let color = ColorCode::new(Color::White, Color::Blue);
+                let (col, row) = (BUFFER_WIDTH / 2 - 9, BUFFER_HEIGHT / 2 - 4);
+                for i in 0..7 {
+                    canvas.plot_str("                  ", col, row + i, color);
+                }
+                canvas.plot_str("PAUSED", col + 6, row + 1, ColorCode::new(Color::Yellow, Color::Blue));
+                self.pause.draw(canvas, col + 2, row + 2);
+            }
+            Status::LevelComplete => {
+                let color = ColorCode::new(Color::White, Color::Blue);
+                let (col, row) = (BUFFER_WIDTH / 2 - 15, BUFFER_HEIGHT / 2 - 3);
+                for i in 0..6 {
+                    canvas.plot_str("                              ", col, row + i, color);
+                }
+                let end = canvas.plot_str("Cleared ", col + 1, row + 1, ColorCode::new(Color::Yellow, Color::Blue));
+                canvas.plot_str(self.level_info.name, end, row + 1, ColorCode::new(Color::Yellow, Color::Blue));
+                let end = canvas.plot_str("Time: ", col + 1, row + 2, color);
+                let end = canvas.plot_num(self.level_seconds, end, row + 2, color);
+                let end = canvas.plot_str("s, par ", end, row + 2, color);
+                let end = canvas.plot_num(self.level_info.par, end, row + 2, color);
+                canvas.plot_str("s", end, row + 2, color);
+                let end = canvas.plot_str("Par bonus: ", col + 1, row + 3, color);
+                canvas.plot_num(self.level_bonus, end, row + 3, color);
+                let end = self.plot_binding(canvas, "Press ", Action::Confirm, col + 1, row + 4, color);
+                canvas.plot_str(" to go on", end, row + 4, color);
+            }
+            Status::EnterInitials => {
+                let color = ColorCode::new(Color::White, Color::Blue);
+                let (col, row) = (BUFFER_WIDTH / 2 - 17, BUFFER_HEIGHT / 2 - 3);
+                for i in 0..8 {
+                    canvas.plot_str("                                  ", col, row + i, color);
+                }
+                let end = canvas.plot_str("New high score! ", col + 2, row + 1, ColorCode::new(Color::Yellow, Color::Blue));
+                canvas.plot_num(self.score(), end, row + 1, ColorCode::new(Color::Yellow, Color::Blue));
+                canvas.plot_str("Your initials:", col + 2, row + 3, color);
+                self.initials.draw(canvas, col + 17, row + 3, color);
+                canvas.plot_str("Type or use arrows, Backspace", col + 2, row + 5, color);
+                canvas.plot_str("to erase, Enter to save", col + 2, row + 6, color);
+            }
+            Status::Normal if self.mode == PlayMode::Linked && self.net.waited() > TICKS_PER_SECOND as u32 / 2 => {
+                let text = "Waiting for the other player...";
+                canvas.plot_str(text, (BUFFER_WIDTH - text.len()) / 2, BUFFER_HEIGHT / 2 + 2, ColorCode::new(Color::Yellow, Color::Black));
+            }
+            Status::Over if self.mode == PlayMode::Linked => {
+                let (col, row) = self.clear_over_lines(canvas);
+                let color = ColorCode::new(Color::White, Color::Black);
+                let end = self.plot_binding(canvas, "Game Over! ", Action::Back, col, row, color);
+                canvas.plot_str(" for title", end, row, color);
+                if let LinkState::Ended(end) = self.net.state() {
+                    canvas.plot_str(end.message(), col, row + 1, ColorCode::new(Color::White, Color::Red));
+                }
+                self.draw_results(canvas);
+            }
+            Status::Over => {
+                let (col, row) = self.clear_over_lines(canvas);
+                let color = ColorCode::new(Color::White, Color::Black);
+                let end = self.plot_binding(canvas, "Game Over! ", Action::Restart, col, row, color);
+                let end = self.plot_binding(canvas, " to retry, ", Action::Back, end, row, color);
+                canvas.plot_str(" for title", end, row, color);
+                let end = canvas.plot_str("Left/Right: level ", col, row + 1, color);
+                let end = canvas.plot_num(self.picked_level as isize + 1, end, row + 1, color);
+                let end = self.plot_binding(canvas, ", ", Action::Confirm, end, row + 1, color);
+                let end = self.plot_binding(canvas, " to play it, ", Action::Edit, end, row + 1, color);
+                let end = self.plot_binding(canvas, " to edit, ", Action::Controls, end, row + 1, color);
+                canvas.plot_str(" for controls", end, row + 1, color);
+                self.draw_results(canvas);
+            }
+            _ => {}
+        }
+    }
+
+    fn draw_title<C: Canvas>(&self, canvas: &mut C) {
+        canvas.clear(ColorCode::new(Color::White, Color::Black));
+        canvas.plot_str("S P A C E   J U N K", BUFFER_WIDTH / 2 - 10, 5, ColorCode::new(Color::Yellow, Color::Black));
+        self.title.draw(canvas, BUFFER_WIDTH / 2 - 6, 9);
+        self.draw_menu_help(canvas);
+    }
+
+    fn draw_connecting<C: Canvas>(&self, canvas: &mut C) {
+        let color = ColorCode::new(Color::White, Color::Black);
+        canvas.clear(color);
+        canvas.plot_str("Versus over serial", BUFFER_WIDTH / 2 - 9, 3, ColorCode::new(Color::Yellow, Color::Black));
+        canvas.plot_str("Waiting for the other machine on COM1...", 12, 7, color);
+        canvas.plot_str("Start both QEMUs with their serial ports joined, e.g.", 12, 9, color);
+        canvas.plot_str("-serial tcp::4555,server,nowait on one and", 14, 10, color);
+        canvas.plot_str("-serial tcp:localhost:4555 on the other.", 14, 11, color);
+        canvas.plot_str("Both play with one side's lives, level and seed.", 12, 13, color);
+        let col = self.plot_binding(canvas, "", Action::Back, 1, BUFFER_HEIGHT - 2, color);
+        canvas.plot_str(" to cancel", col, BUFFER_HEIGHT - 2, color);
+    }
+
+    fn draw_seed_entry<C: Canvas>(&self, canvas: &mut C) {
+        let color = ColorCode::new(Color::White, Color::Black);
+        canvas.clear(color);
+        canvas.plot_str("Play a seed", BUFFER_WIDTH / 2 - 6, 3, ColorCode::new(Color::Yellow, Color::Black));
+        canvas.plot_str("The same seed always plays out the same way.", 18, 7, color);
+        let end = canvas.plot_str("Current seed: ", 18, 9, color);
+        canvas.plot_num(self.settings.seed as isize, end, 9, color);
+        canvas.plot_str("Seed:", 18, 11, color);
+        self.seed_entry.draw(canvas, 29, 11, ColorCode::new(Color::Yellow, Color::Black));
+        let col = self.plot_binding(canvas, "Type a number, ", Action::Confirm, 1, BUFFER_HEIGHT - 2, color);
+        let col = self.plot_binding(canvas, " to play it, ", Action::Back, col, BUFFER_HEIGHT - 2, color);
+        canvas.plot_str(" to go back", col, BUFFER_HEIGHT - 2, color);
+    }
+
+    fn draw_replays<C: Canvas>(&self, canvas: &mut C) {
+        let color = ColorCode::new(Color::White, Color::Black);
+        canvas.clear(color);
+        canvas.plot_str("Replays", BUFFER_WIDTH / 2 - 4, 3, ColorCode::new(Color::Yellow, Color::Black));
+        let col = BUFFER_WIDTH / 2 - 16;
+        if let Some(replay) = self.replay {
+            let end = canvas.plot_str("Seed:     ", col, 6, color);
+            canvas.plot_num(replay.seed as isize, end, 6, color);
+            let end = canvas.plot_str("Level:    ", col, 7, color);
+            canvas.plot_num(replay.level as isize + 1, end, 7, color);
+            let end = canvas.plot_str("Lives:    ", col, 8, color);
+            canvas.plot_num(replay.lives, end, 8, color);
+            let end = canvas.plot_str("Players:  ", col, 9, color);
+            canvas.plot_str(replay.mode.label(), end, 9, color);
+            let end = canvas.plot_str("Inputs:   ", col, 10, color);
+            let end = canvas.plot_num(replay.inputs() as isize, end, 10, color);
+            let end = canvas.plot_str(" over ", end, 10, color);
+            let end = canvas.plot_num(replay.last_tick() / TICKS_PER_SECOND, end, 10, color);
+            canvas.plot_str("s", end, 10, color);
+        } else {
+            canvas.plot_str("Nothing recorded yet. Every run is recorded.", col, 6, color);
+        }
+        self.replay_menu.draw(canvas, col, 13);
+        if let Some(import) = self.import {
+            let end = canvas.plot_str("Listening on COM1... ", col, 18, color);
+            let end = canvas.plot_num(import.received() as isize, end, 18, color);
+            canvas.plot_str(" bytes", end, 18, color);
+        }
+        canvas.plot_str(self.replay_message, col, 19, ColorCode::new(Color::Yellow, Color::Black));
+        let col = self.plot_binding(canvas, "", Action::Confirm, 1, BUFFER_HEIGHT - 2, color);
+        let col = self.plot_binding(canvas, " to choose, ", Action::Back, col, BUFFER_HEIGHT - 2, color);
+        canvas.plot_str(" to go back", col, BUFFER_HEIGHT - 2, color);
+    }
+
+    fn draw_high_scores<C: Canvas>(&self, canvas: &mut C) {
+        let color = ColorCode::new(Color::White, Color::Black);
+        canvas.clear(color);
+        canvas.plot_str("High Scores", BUFFER_WIDTH / 2 - 6, 3, ColorCode::new(Color::Yellow, Color::Black));
+        self.scores.draw(canvas, BUFFER_WIDTH / 2 - 12, 6);
+        if self.confirm_clear {
+            let warning = ColorCode::new(Color::White, Color::Red);
+            let col = self.plot_binding(canvas, "Clear every score? ", Action::Confirm, 1, BUFFER_HEIGHT - 2, warning);
+            canvas.plot_str(" to clear, any other key to keep them", col, BUFFER_HEIGHT - 2, warning);
+        } else {
+            let col = self.plot_binding(canvas, "", Action::Back, 1, BUFFER_HEIGHT - 2, color);
+            let col = self.plot_binding(canvas, " to go back, ", Action::Restart, col, BUFFER_HEIGHT - 2, color);
+            canvas.plot_str(" to clear the table", col, BUFFER_HEIGHT - 2, color);
+        }
+    }
+
+    fn draw_settings<C: Canvas>(&self, canvas: &mut C) {
+        let color = ColorCode::new(Color::White, Color::Black);
+        canvas.clear(color);
+        canvas.plot_str("Settings", BUFFER_WIDTH / 2 - 4, 3, ColorCode::new(Color::Yellow, Color::Black));
+        let (col, row) = (BUFFER_WIDTH / 2 - 16, 7);
+        self.settings_menu.draw(canvas, col, row);
+        canvas.plot_num(self.settings.lives, col + 16, row, color);
+        let end = canvas.plot_num(self.settings.level as isize + 1, col + 16, row + 1, color);
+        canvas.plot_str(self.level_info.name, end + 1, row + 1, color);
+        canvas.plot_str(self.settings.mode.label(), col + 16, row + 2, color);
+        canvas.plot_str(if self.settings.reroll {"Reroll"} else {"Keep"}, col + 16, row + 3, color);
+        let col = self.plot_binding(canvas, "", Action::MoveLeft, 1, BUFFER_HEIGHT - 3, color);
+        let col = self.plot_binding(canvas, "/", Action::MoveRight, col, BUFFER_HEIGHT - 3, color);
+        canvas.plot_str(" to change a value", col, BUFFER_HEIGHT - 3, color);
+        self.draw_menu_help(canvas);
+    }
+
+    fn draw_menu_help<C: Canvas>(&self, canvas: &mut C) {
+        let color = ColorCode::new(Color::White, Color::Black);
+        let col = self.plot_binding(canvas, "", Action::MoveUp, 1, BUFFER_HEIGHT - 2, color);
+        let col = self.plot_binding(canvas, "/", Action::MoveDown, col, BUFFER_HEIGHT - 2, color);
+        let col = self.plot_binding(canvas, " to choose, ", Action::Confirm, col, BUFFER_HEIGHT - 2, color);
+        canvas.plot_str(" to select", col, BUFFER_HEIGHT - 2, color);
+    }
+
+    // Plots `text` followed by the name of the first key bound to `action`.
+    fn plot_binding<C: Canvas>(&self, canvas: &mut C, text: &str, action: Action, col: usize, row: usize, color: ColorCode) -> usize {
+        let col = canvas.plot_str(text, col, row, color);
+        match self.settings.bindings.key_for(action) {
+            Some(key) => plot_key(canvas, key, col, row, color),
+            None => canvas.plot_str("?", col, row, color),
+        }
+    }
+}
+
+// How one wave of shooters plays out.
+#[derive(Copy, Clone)]
+pub struct Wave {
+    pub shooters: usize,
+    pub kinds: usize,
+    pub spawn_freq: isize,
+    pub move_freq: isize,
+    pub duration: isize,
+}
+
+impl Wave {
+    // Each wave brings more shooters, sooner and faster, and unlocks one more kind of shooter.
+    pub fn number(n: isize) -> Self {
+        let n = n.max(1);
+        Self {
+            shooters: (3 + 2 * n) as usize,
+            kinds: (n as usize).min(SHOOTER_WEIGHTS.len()),
+            spawn_freq: (30 - 3 * n).max(8),
+            move_freq: (8 - n / 2).max(2),
+            duration: 500 + 100 * n,
+        }
+    }
+}
+
+const BRITTLE_HITS: u8 = 3;
+
+// Everything a map cell can be. Gates only let things through that are moving in the
+// gate's direction; teleporters send whatever enters them to the other teleporter with
+// the same number; the player slides across ice and projectiles crawl over slow ground.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Tile {
+    Floor,
+    Wall,
+    Brittle(u8),
+    Gate(u8),
+    Teleporter(u8),
+    Ice,
+    Slow,
+}
+
+impl Tile {
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            ' ' => Some(Tile::Floor),
+            '#' => Some(Tile::Wall),
+            '%' => Some(Tile::Brittle(BRITTLE_HITS)),
+            '>' => Some(Tile::Gate(0)),
+            'v' => Some(Tile::Gate(1)),
+            '<' => Some(Tile::Gate(2)),
+            '^' => Some(Tile::Gate(3)),
+            '1'..='9' => Some(Tile::Teleporter(c as u8 - b'0')),
+            '~' => Some(Tile::Ice),
+            ':' => Some(Tile::Slow),
+            _ => None,
+        }
+    }
+
+    pub fn glyph(&self) -> char {
+        match self {
+            Tile::Floor => ' ',
+            Tile::Wall => '#',
+            Tile::Brittle(_) => '%',
+            Tile::Gate(dir) => ['>', 'v', '<', '^'][*dir as usize % 4],
+            Tile::Teleporter(id) => (b'0' + id) as char,
+            Tile::Ice => '~',
+            Tile::Slow => ':',
+        }
+    }
+
+    pub fn color(&self) -> ColorCode {
+        match self {
+            Tile::Floor | Tile::Wall => ColorCode::new(Color::Blue, Color::Black),
+            Tile::Brittle(hits) if *hits < BRITTLE_HITS => ColorCode::new(Color::DarkGray, Color::Black),
+            Tile::Brittle(_) => ColorCode::new(Color::LightGray, Color::Black),
+            Tile::Gate(_) => ColorCode::new(Color::LightBlue, Color::Black),
+            Tile::Teleporter(_) => ColorCode::new(Color::Pink, Color::Black),
+            Tile::Ice => ColorCode::new(Color::LightCyan, Color::Blue),
+            Tile::Slow => ColorCode::new(Color::Brown, Color::Black),
+        }
+    }
+
+    pub fn is_solid(&self) -> bool {
+        matches!(self, Tile::Wall | Tile::Brittle(_))
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Walls {
+    walls: [[Tile; BUFFER_WIDTH]; BUFFER_HEIGHT]
+}
+
+impl Walls {
+    // Characters with no tile are read as floor.
+    pub fn new(map: &str) -> Self {
+        let mut walls = [[Tile::Floor; BUFFER_WIDTH]; BUFFER_HEIGHT];
+        for (row, chars) in map.split('\n').enumerate() {
+            for (col, value) in chars.char_indices() {
+                walls[row][col] = Tile::from_char(value).unwrap_or(Tile::Floor);
+            }
+        }
+        Self {walls}
+    }
+
+    pub fn from_grid(walls: [[Tile; BUFFER_WIDTH]; BUFFER_HEIGHT]) -> Self {
+        Self {walls}
+    }
+
+    pub fn toggle(&mut self, row: usize, col: usize) {
+        if row < BUFFER_HEIGHT && col < BUFFER_WIDTH {
+            self.walls[row][col] = if self.walls[row][col] == Tile::Wall {Tile::Floor} else {Tile::Wall};
+        }
+    }
+
+    pub fn set(&mut self, row: usize, col: usize, tile: Tile) {
+        if row < BUFFER_HEIGHT && col < BUFFER_WIDTH {
+            self.walls[row][col] = tile;
+        }
+    }
+
+    pub fn draw<C: Canvas>(&self, canvas: &mut C) {
+        for row in 0..self.walls.len() {
+            for col in 0..self.walls[row].len() {
+                let tile = self.walls[row][col];
+                canvas.plot(tile.glyph(), col, row, tile.color());
+            }
+        }
+    }
+
+    // Anything off the map counts as a wall.
+    pub fn tile(&self, row: usize, col: usize) -> Tile {
+        if row >= BUFFER_HEIGHT || col >= BUFFER_WIDTH {
+            Tile::Wall
+        } else {
+            self.walls[row][col]
+        }
+    }
+
+    pub fn occupied(&self, row: usize, col: usize) -> bool {
+        self.tile(row, col).is_solid()
+    }
+
+    /// Whether something moving in direction `dir` is stopped from entering the cell.
+    pub fn blocks(&self, row: usize, col: usize, dir: usize) -> bool {
+        match self.tile(row, col) {
+            Tile::Gate(gate) => gate as usize != dir,
+            tile => tile.is_solid(),
+        }
+    }
+
+    // A projectile struck the cell. Brittle walls crumble to floor after enough hits.
+    pub fn hit(&mut self, row: usize, col: usize) {
+        if let Tile::Brittle(hits) = self.tile(row, col) {
+            self.walls[row][col] = if hits > 1 {Tile::Brittle(hits - 1)} else {Tile::Floor};
+        }
+    }
+
+    /// Where something entering the given cell comes out, if the cell is a teleporter.
+    pub fn teleport(&self, row: usize, col: usize) -> Option<(usize, usize)> {
+        if let Tile::Teleporter(id) = self.tile(row, col) {
+            for r in 0..BUFFER_HEIGHT {
+                for c in 0..BUFFER_WIDTH {
+                    if (r, c) != (row, col) && self.walls[r][c] == Tile::Teleporter(id) {
+                        return Some((c, r));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    // Moves a projectile one step, applying gates, teleporters and brittle walls.
+    pub fn advance(&mut self, proj: &mut Projectile) {
+        proj.momentum();
+        if self.blocks(proj.y, proj.x, proj.dir) {
+            self.hit(proj.y, proj.x);
+            proj.remove();
+        } else if let Some((x, y)) = self.teleport(proj.y, proj.x) {
+            proj.move_to(x, y);
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Projectile {
+    x: usize,
+    y: usize,
+    dir: usize, 
+}
+
+impl Projectile {
+    pub fn new() -> Self {
+        Self {x: 100 , y: 100, dir: 0}
+    }
+
+    pub fn draw<C: Canvas>(&self, canvas: &mut C) {
+        canvas.plot('X', self.x, self.y, ColorCode::new(Color::Yellow, Color::Black));
+    }
+
+    pub fn draw_friendly<C: Canvas>(&self, canvas: &mut C) {
+        let glyph = if self.dir.is_multiple_of(2) {'-'} else {'|'};
+        canvas.plot(glyph, self.x, self.y, ColorCode::new(Color::LightCyan, Color::Black));
+    }
+
+    pub fn move_to(&mut self, x: usize, y: usize) {
+        self.x = x;
+        self.y = y;
+    }
+
+    pub fn change_dir(&mut self, dir: usize) {
+        self.dir = dir;
+    }
+
+    // Moving off the top or left edge wraps to a huge coordinate, which every
+    // bounds check in the game already treats as outside the play area.
+    pub fn momentum(&mut self) {
+        let (dx, dy) = DIRECTIONS[self.dir % DIRECTIONS.len()];
+        self.x = self.x.wrapping_add_signed(dx);
+        self.y = self.y.wrapping_add_signed(dy);
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.x < BUFFER_WIDTH && self.y < BUFFER_HEIGHT
+    }
+
+    pub fn occupied(&self, row: usize, col: usize) -> bool {
+        row > 24 || col > 79 || (self.x == col && self.y == row)
+    }
+
+    pub fn remove(&mut self) {
+        self.x = 100;
+        self.y = 100;
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PowerKind {
+    Shield,
+    SlowTime,
+    Bomb,
+}
+
+impl PowerKind {
+    pub fn glyph(&self) -> char {
+        match self {
+            PowerKind::Shield => 'O',
+            PowerKind::SlowTime => 'T',
+            PowerKind::Bomb => 'B',
+        }
+    }
+
+    pub fn color(&self) -> ColorCode {
+        match self {
+            PowerKind::Shield => ColorCode::new(Color::LightBlue, Color::Black),
+            PowerKind::SlowTime => ColorCode::new(Color::LightGreen, Color::Black),
+            PowerKind::Bomb => ColorCode::new(Color::LightRed, Color::Black),
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PowerKind::Shield => "Shield",
+            PowerKind::SlowTime => "Slow",
+            PowerKind::Bomb => "Bomb!",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PowerKind::Shield => "shield",
+            PowerKind::SlowTime => "slow",
+            PowerKind::Bomb => "bomb",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "shield" => Some(PowerKind::Shield),
+            "slow" => Some(PowerKind::SlowTime),
+            "bomb" => Some(PowerKind::Bomb),
+            _ => None,
+        }
+    }
+
+    // How many ticks the effect lasts. A bomb goes off at once, so it has none.
+    pub fn duration(&self) -> isize {
+        match self {
+            PowerKind::Shield => 300,
+            PowerKind::SlowTime => 150,
+            PowerKind::Bomb => 0,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct PowerUp {
+    x: usize,
+    y: usize,
+    kind: PowerKind,
+}
+
+impl PowerUp {
+    pub fn new() -> Self {
+        Self {x: 100, y: 100, kind: PowerKind::Shield}
+    }
+
+    pub fn place(&mut self, x: usize, y: usize, kind: PowerKind) {
+        self.x = x;
+        self.y = y;
+        self.kind = kind;
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.x < BUFFER_WIDTH && self.y < BUFFER_HEIGHT
+    }
+
+    pub fn draw<C: Canvas>(&self, canvas: &mut C) {
+        canvas.plot(self.kind.glyph(), self.x, self.y, self.kind.color());
+    }
+
+    pub fn remove(&mut self) {
+        self.x = 100;
+        self.y = 100;
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ShooterKind {
+    Basic,
+    Spreader,
+    Sniper,
+    Spiral,
+    Burst,
+}
+
+impl ShooterKind {
+    pub fn glyph(&self) -> char {
+        match self {
+            ShooterKind::Basic => 'S',
+            ShooterKind::Spreader => 'V',
+            ShooterKind::Sniper => 'N',
+            ShooterKind::Spiral => '@',
+            ShooterKind::Burst => 'M',
+        }
+    }
+
+    pub fn color(&self) -> ColorCode {
+        match self {
+            ShooterKind::Basic => ColorCode::new(Color::Magenta, Color::Black),
+            ShooterKind::Spreader => ColorCode::new(Color::Cyan, Color::Black),
+            ShooterKind::Sniper => ColorCode::new(Color::Red, Color::Black),
+            ShooterKind::Spiral => ColorCode::new(Color::Pink, Color::Black),
+            ShooterKind::Burst => ColorCode::new(Color::Brown, Color::Black),
+        }
+    }
+
+    pub fn points(&self) -> isize {
+        match self {
+            ShooterKind::Basic => 50,
+            ShooterKind::Spreader => 75,
+            ShooterKind::Sniper => 100,
+            ShooterKind::Spiral => 75,
+            ShooterKind::Burst => 100,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Movement {
+    RandomWalk,
+    Patrol,
+    Chase,
+    Flee,
+}
+
+const MOVEMENTS: [Movement; 4] = [Movement::RandomWalk, Movement::Patrol, Movement::Chase, Movement::Flee];
+
+// Space junk floats diagonally, bouncing off walls, until the player picks it up.
+const JUNK_VALUES: [isize; 3] = [10, 25, 50];
+
+#[derive(Copy, Clone)]
+pub struct Junk {
+    x: usize,
+    y: usize,
+    dir: usize,
+    value: isize,
+}
+
+impl Junk {
+    pub fn new() -> Self {
+        Self {x: 100, y: 100, dir: 4, value: 0}
+    }
+
+    pub fn place(&mut self, x: usize, y: usize, dir: usize, value: isize) {
+        self.x = x;
+        self.y = y;
+        self.dir = dir;
+        self.value = value;
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.x < BUFFER_WIDTH && self.y < BUFFER_HEIGHT
+    }
+
+    pub fn remove(&mut self) {
+        self.x = 100;
+        self.y = 100;
+    }
+
+    // Flips whichever half of the direction runs into a wall, or both in a corner.
+    pub fn drift(&mut self, walls: &Walls) {
+        let (dx, dy) = DIRECTIONS[self.dir];
+        for (bx, by) in [(dx, dy), (-dx, dy), (dx, -dy), (-dx, -dy)] {
+            let nx = self.x.wrapping_add_signed(bx);
+            let ny = self.y.wrapping_add_signed(by);
+            if !walls.occupied(ny, nx) {
+                self.dir = DIRECTIONS.iter().position(|d| *d == (bx, by)).unwrap_or(self.dir);
+                self.x = nx;
+                self.y = ny;
+                return;
+            }
+        }
+    }
+
+    pub fn draw<C: Canvas>(&self, canvas: &mut C) {
+        let (glyph, color) = match self.value {
+            v if v >= 50 => ('$', Color::Yellow),
+            v if v >= 25 => ('&', Color::LightGray),
+            _ => ('+', Color::Brown),
+        };
+        canvas.plot(glyph, self.x, self.y, ColorCode::new(color, Color::Black));
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Shooter {
+    x: usize, 
+    y: usize,
+    kind: ShooterKind,
+    phase: usize,
+    movement: Movement,
+    heading: usize,
+}
+
+impl Shooter {
+    pub fn new() -> Self {
+        Self::of_kind(ShooterKind::Basic)
+    }
+
+    pub fn of_kind(kind: ShooterKind) -> Self {
+        Self {x: 100, y : 100, kind, phase: 0, movement: Movement::RandomWalk, heading: 0}
+    }
+
+    pub fn set_movement(&mut self, movement: Movement) {
+        self.movement = movement;
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.x < BUFFER_WIDTH && self.y < BUFFER_HEIGHT
+    }
+
+    pub fn move_to(&mut self, x: usize, y: usize) {
+        self.x = x;
+        self.y = y;
+    }
+
+    // A projectile one step away in the given direction, moving that way.
+    pub fn shoot(&self, dir: usize) -> Projectile {
+        let mut proj = Projectile::new();
+        proj.move_to(self.x, self.y);
+        proj.change_dir(dir);
+        proj.momentum();
+        proj
+    }
+
+    // The direction out of the eight that points closest to the target.
+    pub fn aim(&self, target_x: usize, target_y: usize) -> usize {
+        let dx = target_x as isize - self.x as isize;
+        let dy = target_y as isize - self.y as isize;
+        let sx = if dy.abs() > 2 * dx.abs() {0} else {dx.signum()};
+        let sy = if dx.abs() > 2 * dy.abs() {0} else {dy.signum()};
+        DIRECTIONS.iter().position(|d| *d == (sx, sy)).unwrap_or(1)
+    }
+
+    // Returns whatever this shooter fires on the given tick. Each kind has its own pattern and cadence.
+    pub fn fire(&mut self, tick: isize, target_x: usize, target_y: usize) -> [Option<Projectile>; 4] {
+        let mut shots = [None; 4];
+        match self.kind {
+            ShooterKind::Basic => if tick % MOVE_SHOOT_FREQ == 0 {
+                shots = [Some(self.shoot(1)), Some(self.shoot(2)), Some(self.shoot(3)), Some(self.shoot(0))];
+            },
+            ShooterKind::Spreader => if tick % 8 == 0 {
+                shots = [Some(self.shoot(4)), Some(self.shoot(5)), Some(self.shoot(6)), Some(self.shoot(7))];
+            },
+            ShooterKind::Sniper => if tick % 12 == 0 {
+                shots[0] = Some(self.shoot(self.aim(target_x, target_y)));
+            },
+            ShooterKind::Spiral => if tick % 3 == 0 {
+                shots[0] = Some(self.shoot(self.phase));
+                self.phase = (self.phase + 1) % DIRECTIONS.len();
+            },
+            ShooterKind::Burst => if tick % 24 < 6 && tick % 2 == 0 {
+                shots[0] = Some(self.shoot(self.aim(target_x, target_y)));
+            },
+        }
+        shots
+    }
+
+    pub fn draw<C: Canvas>(&self, canvas: &mut C) {
+        canvas.plot(self.kind.glyph(), self.x, self.y, self.kind.color());
+    }
+
+    pub fn remove(&mut self) {
+        self.x = 100;
+        self.y = 100;
+    }
+
+    // Moves one cell in the given direction if no wall is in the way.
+    fn step(&mut self, dir: usize, walls: &Walls) -> bool {
+        let (dx, dy) = DIRECTIONS[dir];
+        let nx = self.x.wrapping_add_signed(dx);
+        let ny = self.y.wrapping_add_signed(dy);
+        if walls.blocks(ny, nx, dir) {
+            false
+        } else {
+            self.move_to(nx, ny);
+            true
+        }
+    }
+
+    // Takes one step according to this shooter's movement behavior. Shooters never
+    // enter a wall cell, so a walled arena keeps them on screen.
+    pub fn shift(&mut self, rng: &mut SmallRng, walls: &Walls, target_x: usize, target_y: usize) {
+        match self.movement {
+            Movement::RandomWalk => {
+                self.step(rng.next_u32() as usize % 4, walls);
+            }
+            Movement::Patrol => {
+                if !self.step(self.heading, walls) {
+                    self.heading = (self.heading + 2) % 4;
+                    self.step(self.heading, walls);
+                }
+            }
+            Movement::Chase | Movement::Flee => {
+                let dx = target_x as isize - self.x as isize;
+                let dy = target_y as isize - self.y as isize;
+                if self.movement == Movement::Chase && dx.abs() + dy.abs() <= 1 {
+                    return;
+                }
+                let toward_x = if dx > 0 {0} else {2};
+                let toward_y = if dy > 0 {1} else {3};
+                let (first, second) = if dx.abs() >= dy.abs() {(toward_x, toward_y)} else {(toward_y, toward_x)};
+                let (first, second) = if self.movement == Movement::Flee {
+                    ((first + 2) % 4, (second + 2) % 4)
+                } else {
+                    (first, second)
+                };
+                if !self.step(first, walls) {
+                    self.step(second, walls);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Player {
+    x: usize,
+    y: usize,
+    dir: usize,
+    invulnerable: isize,
+}
+
+impl Player {
+    pub fn new() -> Self {
+        Self {x: BUFFER_WIDTH / 2, y: BUFFER_HEIGHT / 2, dir: 3, invulnerable: 0}
+    }
+
+    pub fn move_to(&mut self, x: usize, y: usize) {
+        self.x = x;
+        self.y = y;
+    }
+
+    // Blinks while invulnerable.
+    pub fn visible(&self) -> bool {
+        (self.invulnerable / 3) % 2 == 0
+    }
+
+    // Fires in the direction the player last moved, using the same directions as Projectile.
+    pub fn shoot(&self) -> Projectile {
+        let mut proj = Projectile::new();
+        proj.move_to(self.x, self.y);
+        proj.change_dir(self.dir);
+        proj.momentum();
+        proj
+    }
+
+    pub fn is_colliding(&self, walls: &Walls) -> bool {
+        walls.occupied(self.y, self.x)
+    }
+
+    pub fn step(&mut self, dir: usize) {
+        match dir {
+            0 => self.right(),
+            1 => self.down(),
+            2 => self.left(),
+            _ => self.up(),
+        }
+    }
+
+    pub fn proj_collision(&self, proj: &Projectile) -> bool {
+        proj.occupied(self.y, self.x)
+    }
+
+    pub fn down(&mut self) {
+        self.y += 1;
+        self.dir = 1;
+    }
+
+    pub fn up(&mut self) {
+        self.y = self.y.wrapping_sub(1);
+        self.dir = 3;
+    }
+
+    pub fn left(&mut self) {
+        self.x = self.x.wrapping_sub(1);
+        self.dir = 2;
+    }
+
+    pub fn right(&mut self) {
+        self.x += 1;
+        self.dir = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use canvas::{GridCanvas, FrameBuffer};
+    use link::RamLink;
+    use pc_keyboard::KeyCode;
+
+    fn started(lives: isize) -> Game {
+        let mut game = Game::with_lives(lives);
+        game.key(DecodedKey::Unicode('\n'));
+        assert_eq!(game.status(), Status::Normal);
+        game
+    }
+
+    #[test]
+    fn draws_the_player_and_hud() {
+        let mut game = started(3);
+        let mut canvas = GridCanvas::new();
+        game.tick(&mut canvas);
+        assert_eq!(canvas.peek(BUFFER_WIDTH / 2, BUFFER_HEIGHT / 2).0, '*');
+        assert!(canvas.shows("Score:", 1, 0));
+        assert!(canvas.shows("Lives:", 20, 0));
+    }
+
+    #[test]
+    fn shooters_fire_over_time() {
+        let mut game = started(1_000_000);
+        let mut canvas = GridCanvas::new();
+        let mut most = 0;
+        for _ in 0..600 {
+            game.tick(&mut canvas);
+            most = most.max(canvas.count('X'));
+        }
+        assert!(most > 4, "{}", most);
+    }
+
+    #[test]
+    fn shots_leave_the_screen() {
+        let mut game = started(3);
+        let mut canvas = GridCanvas::new();
+        game.key(DecodedKey::Unicode(' '));
+        for _ in 0..30 {
+            game.tick(&mut canvas);
+        }
+        assert_eq!(canvas.count('|'), 0);
+    }
+
+    #[test]
+    fn frame_buffer_sends_only_changes() {
+        let mut game = started(3);
+        let mut frames = FrameBuffer::new();
+        let mut screen = GridCanvas::new();
+        game.tick(&mut frames);
+        let first = frames.flush(&mut screen);
+        game.tick(&mut frames);
+        let second = frames.flush(&mut screen);
+        assert!(first > 100 && second < 100, "{} {}", first, second);
+        assert_eq!(screen.peek(BUFFER_WIDTH / 2, BUFFER_HEIGHT / 2).0, '*');
+    }
+
+    #[test]
+    fn bomb_keeps_a_running_shield() {
+        let mut game = started(3);
+        let (x, y) = (game.players[0].x, game.players[0].y);
+        for (slot, kind) in [PowerKind::Shield, PowerKind::Bomb].into_iter().enumerate() {
+            game.power_ups[slot].place(x, y, kind);
+            game.grid.add_pickup(x, y, Pickup::PowerUp(slot));
+            game.collect_power_ups();
+        }
+        assert!(game.power_active(PowerKind::Shield));
+        assert!(game.power_ups.iter().all(|p| !p.is_live()));
+    }
+
+    #[test]
+    fn shield_takes_every_shot_in_the_cell() {
+        let mut game = started(3);
+        let (x, y) = (game.players[0].x, game.players[0].y);
+        game.players[0].invulnerable = 0;
+        game.active_power = Some(PowerKind::Shield);
+        for (px, py) in [(x, y), (x + 5, y), (x, y)] {
+            let mut proj = Projectile::new();
+            proj.move_to(px, py);
+            game.projectiles.spawn(proj);
+        }
+        game.map();
+        game.projectile_hits();
+        assert_eq!(game.lives[0], 3);
+        assert_eq!(game.active_power, None);
+        assert_eq!(game.projectiles.len(), 1);
+        assert_eq!(game.grid.projectiles(x, y), 0);
+        game.map();
+        assert_eq!(game.grid.projectiles(x + 5, y), 1);
+        let mut proj = Projectile::new();
+        proj.move_to(x, y);
+        game.projectiles.spawn(proj);
+        game.map();
+        game.projectile_hits();
+        assert_eq!(game.lives[0], 2);
+    }
+
+    #[test]
+    fn basic_shooter_fires_from_the_corner() {
+        let mut shooter = Shooter::of_kind(ShooterKind::Basic);
+        shooter.move_to(0, 0);
+        let shots = shooter.fire(0, 40, 12);
+        let live: Vec<bool> = shots.iter().map(|shot| shot.is_some_and(|p| p.is_live())).collect();
+        assert_eq!(live, [true, false, false, true]);
+    }
+
+    #[test]
+    fn players_look_like_nothing_else() {
+        let mut canvas = GridCanvas::new();
+        let mut junk = Junk::new();
+        for (i, value) in JUNK_VALUES.iter().enumerate() {
+            junk.place(i, 0, 4, *value);
+            junk.draw(&mut canvas);
+        }
+        let mut proj = Projectile::new();
+        for dir in 0..2 {
+            proj.move_to(dir, 1);
+            proj.change_dir(dir);
+            proj.draw_friendly(&mut canvas);
+        }
+        proj.move_to(2, 1);
+        proj.draw(&mut canvas);
+        let drawn = (0..BUFFER_WIDTH).flat_map(|col| [canvas.peek(col, 0).0, canvas.peek(col, 1).0]);
+        let tiles = "#%>v<^123456789~:".chars();
+        let powers = [PowerKind::Shield, PowerKind::SlowTime, PowerKind::Bomb].map(|kind| kind.glyph());
+        let shooters = [ShooterKind::Basic, ShooterKind::Spreader, ShooterKind::Sniper, ShooterKind::Spiral,
+            ShooterKind::Burst].map(|kind| kind.glyph());
+        let others: Vec<char> = drawn.chain(tiles).chain(powers).chain(shooters).filter(|c| *c != ' ').collect();
+        for (glyph, _) in PLAYER_LOOKS {
+            assert!(!others.contains(&glyph), "{}", glyph);
+        }
+        assert_ne!(PLAYER_LOOKS[0].0, PLAYER_LOOKS[1].0);
+    }
+
+    #[test]
+    fn tiles_stay_small() {
+        // The whole map is copied into every level and editor, so each cell is kept to two bytes.
+        assert_eq!(core::mem::size_of::<Tile>(), 2);
+    }
+
+    #[test]
+    fn runs_every_level_for_a_while() {
+        let keys = [KeyCode::ArrowLeft, KeyCode::ArrowUp, KeyCode::ArrowRight, KeyCode::ArrowDown];
+        for level in 0..LEVELS.len() {
+            let mut game = started(1_000_000);
+            game.load_level(level);
+            let mut canvas = GridCanvas::new();
+            for i in 0..3000 {
+                if i % 7 == 0 {
+                    game.key(DecodedKey::RawKey(keys[i / 70 % keys.len()]));
+                }
+                if i % 11 == 0 {
+                    game.key(DecodedKey::Unicode(' '));
+                }
+                game.tick(&mut canvas);
+            }
+            assert!(canvas.shows("Score:", 1, 0));
+        }
+    }
+
+    #[test]
+    fn shooters_never_pass_the_cap() {
+        // Late waves bring more shooters than the arena can hold.
+        let mut game = started(1_000_000);
+        game.wave = 40;
+        game.level_first_wave = 40;
+        let mut canvas = GridCanvas::new();
+        for _ in 0..10_000 {
+            game.tick(&mut canvas);
+            assert!(game.shooters.len() <= MAX_SHOOTERS);
+        }
+        let stats = game.shooter_stats();
+        assert_eq!(stats.peak, MAX_SHOOTERS);
+        assert!(stats.dropped > 0);
+    }
+
+    // Puts an enemy projectile on a cell and checks it against the players.
+    fn shoot_at(game: &mut Game, x: usize, y: usize) {
+        let mut proj = Projectile::new();
+        proj.move_to(x, y);
+        game.projectiles.spawn(proj);
+        game.map();
+        game.projectile_hits();
+    }
+
+    #[test]
+    fn a_hit_costs_a_life_and_respawns_invulnerable() {
+        let mut game = started(3);
+        game.players[0].invulnerable = 0;
+        game.players[0].move_to(10, 10);
+        shoot_at(&mut game, 10, 10);
+        let (x, y) = game.spawn_point(0);
+        assert_eq!(game.lives[0], 2);
+        assert_eq!((game.players[0].x, game.players[0].y), (x, y));
+        assert_eq!(game.players[0].invulnerable, INVULNERABLE_TICKS);
+        shoot_at(&mut game, x, y);
+        assert_eq!(game.lives[0], 2);
+        assert_eq!(game.players[0].invulnerable, INVULNERABLE_TICKS - 1);
+        let mut canvas = GridCanvas::new();
+        game.tick(&mut canvas);
+        assert_eq!(canvas.peek(27, 0).0, '2');
+    }
+
+    #[test]
+    fn the_last_life_ends_the_run() {
+        let mut game = started(1);
+        game.players[0].invulnerable = 0;
+        let (x, y) = (game.players[0].x, game.players[0].y);
+        shoot_at(&mut game, x, y);
+        assert_eq!(game.lives[0], 0);
+        assert_eq!(game.status(), Status::Over);
+    }
+
+    #[test]
+    fn shooters_stay_off_walls_and_on_the_map() {
+        // A wall across the middle and no border, so only the edge of the map holds them in.
+        let mut map = [[Tile::Floor; BUFFER_WIDTH]; BUFFER_HEIGHT];
+        for cell in map[12][..BUFFER_WIDTH - 1].iter_mut() {
+            *cell = Tile::Wall;
+        }
+        let walls = Walls::from_grid(map);
+        let mut rng = SmallRng::seed_from_u64(5);
+        for movement in MOVEMENTS {
+            for (x, y) in [(0, 0), (BUFFER_WIDTH - 1, BUFFER_HEIGHT - 1), (40, 11)] {
+                let mut shootr = Shooter::new();
+                shootr.set_movement(movement);
+                shootr.move_to(x, y);
+                for i in 0..500 {
+                    shootr.shift(&mut rng, &walls, i % BUFFER_WIDTH, i * 7 % BUFFER_HEIGHT);
+                    assert!(shootr.is_live() && !walls.occupied(shootr.y, shootr.x),
+                        "{:?} reached {}, {}", movement, shootr.x, shootr.y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shooters_chase_flee_and_patrol() {
+        let walls = Walls::from_grid([[Tile::Floor; BUFFER_WIDTH]; BUFFER_HEIGHT]);
+        let mut rng = SmallRng::seed_from_u64(5);
+        let moved = |movement, x, rng: &mut SmallRng| {
+            let mut shootr = Shooter::new();
+            shootr.set_movement(movement);
+            shootr.move_to(x, 10);
+            shootr.shift(rng, &walls, 20, 10);
+            (shootr.x, shootr.y)
+        };
+        assert_eq!(moved(Movement::Chase, 10, &mut rng), (11, 10));
+        assert_eq!(moved(Movement::Chase, 19, &mut rng), (19, 10));
+        assert_eq!(moved(Movement::Flee, 10, &mut rng), (9, 10));
+        assert_eq!(moved(Movement::Patrol, 10, &mut rng), (11, 10));
+        // A patrol turns back at the edge of the map.
+        assert_eq!(moved(Movement::Patrol, BUFFER_WIDTH - 1, &mut rng), (BUFFER_WIDTH - 2, 10));
+    }
+
+    #[test]
+    fn waves_ramp_up() {
+        for n in 1..20 {
+            let (wave, next) = (Wave::number(n), Wave::number(n + 1));
+            assert!(next.shooters > wave.shooters && next.duration > wave.duration);
+            assert!(next.kinds >= wave.kinds && next.kinds <= SHOOTER_WEIGHTS.len());
+            assert!(next.spawn_freq <= wave.spawn_freq && next.spawn_freq >= 8);
+            assert!(next.move_freq <= wave.move_freq && next.move_freq >= 2);
+        }
+        assert_eq!(Wave::number(0).shooters, Wave::number(1).shooters);
+        assert_eq!(Wave::number(1).kinds, 1);
+    }
+
+    #[test]
+    fn waves_end_when_cleared_or_out_of_time() {
+        let mut game = started(3);
+        game.level_info.waves = 10;
+        for _ in 0..WAVE_BANNER_TICKS {
+            game.update_wave();
+        }
+        game.update_wave();
+        assert_eq!(game.wave_spawned, 1);
+        // Destroying the whole wave ends it early.
+        game.wave_spawned = Wave::number(1).shooters;
+        game.shooters.clear();
+        game.update_wave();
+        assert_eq!((game.wave, game.score[0], game.banner), (2, WAVE_BONUS, WAVE_BANNER_TICKS));
+        for _ in 0..WAVE_BANNER_TICKS {
+            game.update_wave();
+        }
+        // A wave that is still going ends when its time runs out.
+        game.tick_count += Wave::number(2).duration;
+        game.update_wave();
+        assert_eq!((game.wave, game.score[0]), (3, WAVE_BONUS * 3));
+    }
+
+    // Drops a piece of junk under the first player and picks it up.
+    fn pick_up_junk(game: &mut Game, value: isize) {
+        let (x, y) = (game.players[0].x, game.players[0].y);
+        game.junk[0].place(x, y, 4, value);
+        game.grid.add_pickup(x, y, Pickup::Junk(0));
+        game.collect_junk();
+    }
+
+    #[test]
+    fn quick_pickups_build_a_combo() {
+        let mut game = started(3);
+        pick_up_junk(&mut game, 10);
+        game.tick_count += COMBO_WINDOW;
+        pick_up_junk(&mut game, 10);
+        game.tick_count += 1;
+        pick_up_junk(&mut game, 25);
+        assert_eq!((game.combo, game.score[0]), (3, 10 + 2 * 10 + 3 * 25));
+        game.tick_count += COMBO_WINDOW + 1;
+        pick_up_junk(&mut game, 50);
+        assert_eq!((game.combo, game.score[0], game.junk_collected), (1, 155, 4));
+        for _ in 0..MAX_COMBO {
+            pick_up_junk(&mut game, 10);
+        }
+        assert_eq!(game.combo, MAX_COMBO);
+    }
+
+    #[test]
+    fn surviving_scores_every_second() {
+        let mut game = started(3);
+        // Hold the first wave back so nothing else can score.
+        game.banner = 10 * TICKS_PER_SECOND;
+        for _ in 0..3 * TICKS_PER_SECOND {
+            game.update();
+        }
+        assert_eq!(game.junk_collected, 0);
+        assert_eq!(game.score[0], 3 * SURVIVAL_POINTS);
+    }
+
+    #[test]
+    fn game_over_picks_a_level_through_actions() {
+        let mut game = started(1);
+        game.status = Status::Over;
+        game.action(Action::MoveLeft);
+        assert_eq!(game.picked_level, LEVELS.len() - 1);
+        game.key(DecodedKey::RawKey(KeyCode::ArrowRight));
+        game.key(DecodedKey::RawKey(KeyCode::ArrowRight));
+        assert_eq!(game.picked_level, 1);
+        // Digits aren't bound to anything, so they do nothing here.
+        game.key(DecodedKey::Unicode('3'));
+        assert_eq!(game.status(), Status::Over);
+        game.key(DecodedKey::Unicode('\n'));
+        assert_eq!((game.status(), game.level), (Status::Normal, 1));
+        assert_eq!(game.replay.unwrap().level, 1);
+    }
+
+    #[test]
+    fn pauses_and_resumes() {
+        let mut game = Game::new();
+        assert_eq!(game.status(), Status::Title);
+        game.action(Action::Confirm);
+        assert_eq!(game.status(), Status::Normal);
+        game.action(Action::Pause);
+        assert_eq!(game.status(), Status::Paused);
+        let ticks = game.tick_count;
+        game.tick(&mut GridCanvas::new());
+        assert_eq!(game.tick_count, ticks);
+        game.action(Action::Confirm);
+        assert_eq!(game.status(), Status::Normal);
+        // Up from Resume wraps round to Quit to title.
+        game.action(Action::Back);
+        game.action(Action::MoveUp);
+        game.action(Action::Confirm);
+        assert_eq!(game.status(), Status::Title);
+    }
+
+    #[test]
+    fn game_over_leaves_the_hud_alone() {
+        let mut game = started(1);
+        game.status = Status::Over;
+        let mut canvas = GridCanvas::new();
+        game.tick(&mut canvas);
+        assert!(canvas.shows("Score:", 1, 0) && canvas.shows("Lives:", 20, 0));
+        assert!(canvas.shows("Game Over! R to retry, Esc for title", BUFFER_WIDTH / 2 - 32, BUFFER_HEIGHT / 2 + 5));
+        game.action(Action::Back);
+        assert_eq!(game.status(), Status::Title);
+    }
+
+    #[test]
+    fn finished_levels_lead_to_the_next() {
+        let mut game = started(3);
+        game.complete_level();
+        assert_eq!(game.status(), Status::LevelComplete);
+        game.action(Action::Confirm);
+        assert_eq!((game.status(), game.level), (Status::Normal, 1));
+    }
+
+    #[test]
+    fn clearing_high_scores_needs_confirming() {
+        let mut game = Game::new();
+        game.scores.insert(Score {initials: *b"ABC", points: 500, seed: 1});
+        game.status = Status::HighScores;
+        game.action(Action::Restart);
+        game.action(Action::Back);
+        assert_eq!((game.status(), game.scores.entries().len()), (Status::HighScores, 1));
+        game.action(Action::Restart);
+        game.action(Action::Confirm);
+        assert_eq!(game.scores.entries().len(), 0);
+        assert!(game.scores_changed);
+        game.action(Action::Back);
+        assert_eq!(game.status(), Status::Title);
+    }
+
+    #[test]
+    fn level_errors_and_the_replay_tag_keep_clear_of_the_second_hud() {
+        let mut game = Game::with_lives(3);
+        game.set_mode(PlayMode::Competitive);
+        game.key(DecodedKey::Unicode('\n'));
+        game.level_error = Some(LevelError::MapTooWide(12));
+        game.playback = Some(Playback::new());
+        let mut canvas = GridCanvas::new();
+        game.tick(&mut canvas);
+        let bottom = BUFFER_HEIGHT - 1;
+        assert!(canvas.shows("Score:", 1, bottom) && canvas.shows("Lives:", 20, bottom));
+        assert!(canvas.shows("REPLAY", 56, bottom) && canvas.shows("Seed ", 64, bottom));
+        let row: String = (0..BUFFER_WIDTH).map(|col| canvas.peek(col, BUFFER_HEIGHT / 2 + 1).0).collect();
+        assert!(row.contains("Level error: map row longer than 80 columns on line 12"), "{}", row);
+    }
+
+    #[test]
+    fn each_shot_takes_one_of_the_shooters_in_a_cell() {
+        let mut game = started(3);
+        game.shooters.clear();
+        for _ in 0..2 {
+            let mut shootr = Shooter::of_kind(ShooterKind::Basic);
+            shootr.move_to(30, 5);
+            game.shooters.spawn(shootr);
+        }
+        game.map();
+        assert_eq!(game.grid.shooters(30, 5), 2);
+        for left in [1, 0] {
+            let mut shot = Projectile::new();
+            shot.move_to(30, 5);
+            game.player_projectiles[0][0] = shot;
+            game.player_shot_hits();
+            assert!(!game.player_projectiles[0][0].is_live());
+            assert_eq!((game.shooters.len(), game.grid.shooters(30, 5)), (left, left));
+        }
+    }
+
+    #[test]
+    fn the_grid_walls_follow_the_level() {
+        let game = started(3);
+        for y in 0..BUFFER_HEIGHT {
+            for x in 0..BUFFER_WIDTH {
+                assert_eq!(game.grid.wall(x, y), game.walls.occupied(y, x), "{} {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn restarting_rerolls_a_played_seed_unless_told_to_keep_it() {
+        let mut game = Game::new();
+        game.set_seed(77);
+        game.start_run();
+        assert_eq!(game.seed(), 77);
+        game.start_run();
+        assert_eq!(game.seed(), seed::mix(77));
+        game.start_run();
+        assert_eq!(game.seed(), seed::mix(seed::mix(77)));
+        game.settings.reroll = false;
+        game.set_seed(77);
+        for _ in 0..3 {
+            game.start_run();
+            assert_eq!(game.seed(), 77);
+        }
+    }
+
+    #[test]
+    fn watching_a_replay_repeats_the_run_tick_for_tick() {
+        let mut game = Game::new();
+        game.settings.lives = 9;
+        game.set_seed(2024);
+        game.start_run();
+        let script = [
+            (3, DecodedKey::RawKey(KeyCode::ArrowLeft)), (3, DecodedKey::Unicode(' ')),
+            (40, DecodedKey::RawKey(KeyCode::ArrowUp)), (41, DecodedKey::RawKey(KeyCode::ArrowUp)),
+            (90, DecodedKey::Unicode(' ')), (150, DecodedKey::RawKey(KeyCode::ArrowRight)),
+            (151, DecodedKey::Unicode(' ')), (260, DecodedKey::RawKey(KeyCode::ArrowDown)),
+        ];
+        let mut canvas = GridCanvas::new();
+        let mut hashes = Vec::new();
+        for tick in 0..400 {
+            for (_, key) in script.iter().filter(|(at, _)| *at == tick) {
+                game.key(*key);
+            }
+            game.tick(&mut canvas);
+            hashes.push(game.state_hash());
+        }
+        assert_eq!(game.replay().unwrap().inputs(), script.len());
+        game.watch_replay();
+        assert!(game.playback.is_some());
+        for (tick, hash) in hashes.iter().enumerate() {
+            game.tick(&mut canvas);
+            assert_eq!(game.state_hash(), *hash, "tick {}", tick);
+        }
+    }
+
+    #[test]
+    fn a_replay_keeps_sending_through_a_new_run() {
+        let mut game = Game::new();
+        game.set_seed(5);
+        game.start_run();
+        game.key(DecodedKey::RawKey(KeyCode::ArrowUp));
+        let sent = *game.replay().unwrap();
+        game.status = Status::Replays;
+        game.action(Action::MoveDown);
+        game.action(Action::Confirm);
+        assert_eq!(game.replay_message, "Sending...");
+        game.start_run();
+        assert_ne!(game.replay().unwrap().seed, sent.seed);
+        let (mut link, mut other) = (RamLink::new(), RamLink::new());
+        let mut import = ReplayImport::new();
+        let mut received = None;
+        while game.export.is_some() {
+            game.link(&mut link);
+            link.transfer(&mut other);
+            while let Some(byte) = other.receive() {
+                received = import.push(byte).or(received);
+            }
+        }
+        assert_eq!(game.replay_message, "Replay sent");
+        let received = received.unwrap().unwrap();
+        assert_eq!((received.seed, received.inputs()), (sent.seed, sent.inputs()));
+    }
+}
@@ -1,49 +1,80 @@
-#![no_std]
-#![no_main]
-
-use pc_keyboard::DecodedKey;
-use pluggable_interrupt_os::HandlerTable;
-use pluggable_interrupt_os::vga_buffer::clear_screen;
-use space_junk::Game;
-use crossbeam::atomic::AtomicCell;
-
-#[no_mangle]
-pub extern "C" fn _start() -> ! {
-    HandlerTable::new()
-        .keyboard(key)
-        .timer(tick)
-        .startup(startup)
-        .cpu_loop(cpu_loop)
-        .start()
-}
+#![cfg_attr(target_os = "none", no_std)]
+#![cfg_attr(target_os = "none", no_main)]
+
+// Only the bare-metal build is a kernel. On the host there is nothing to boot, and the game is
+// exercised through the library's tests instead.
+#[cfg(not(target_os = "none"))]
+fn main() {}
+
+#[cfg(target_os = "none")]
+mod kernel {
+    use pc_keyboard::DecodedKey;
+    use pluggable_interrupt_os::HandlerTable;
+    use pluggable_interrupt_os::vga_buffer::clear_screen;
+    use pluggable_interrupt_os::serial_println;
+    use space_junk::Game;
+    use space_junk::canvas::{FrameBuffer, VgaCanvas};
+    use space_junk::input::InputQueue;
+    use space_junk::link::Com1;
+    use space_junk::nvram::Cmos;
+    use space_junk::scores::ScoreTable;
+    use space_junk::seed::hardware_seed;
+    use crossbeam::atomic::AtomicCell;
 
-static LAST_KEY: AtomicCell<Option<DecodedKey>> = AtomicCell::new(None);
-static TICKS: AtomicCell<usize> = AtomicCell::new(0);
+    #[no_mangle]
+    pub extern "C" fn _start() -> ! {
+        HandlerTable::new()
+            .keyboard(key)
+            .timer(tick)
+            .startup(startup)
+            .cpu_loop(cpu_loop)
+            .start()
+    }
+
+    static KEYS: InputQueue<64> = InputQueue::new();
+    static TICKS: AtomicCell<usize> = AtomicCell::new(0);
+    static SAVED_SCORES: AtomicCell<Option<ScoreTable>> = AtomicCell::new(None);
 
-fn cpu_loop() -> ! {
-    let mut kernel = Game::new();
-    let mut last_tick = 0;
-    loop {
-        if let Some(key) = LAST_KEY.load() {
-            LAST_KEY.store(None);
-            kernel.key(key);
+    fn cpu_loop() -> ! {
+        let mut kernel = Game::new();
+        kernel.set_seed(hardware_seed());
+        if let Some(scores) = SAVED_SCORES.take() {
+            kernel.set_scores(scores);
         }
-        let current_tick = TICKS.load();
-        if current_tick > last_tick {
-            last_tick = current_tick;
-            kernel.tick();
+        let mut screen = FrameBuffer::new();
+        let mut last_tick = 0;
+        let mut dropped = 0;
+        loop {
+            while let Some(key) = KEYS.pop() {
+                kernel.key(key);
+            }
+            if KEYS.overflows() != dropped {
+                dropped = KEYS.overflows();
+                serial_println!("Input queue full: {} keys dropped so far", dropped);
+            }
+            kernel.link(&mut Com1);
+            let current_tick = TICKS.load();
+            if current_tick > last_tick {
+                last_tick = current_tick;
+                kernel.tick(&mut screen);
+                screen.flush(&mut VgaCanvas);
+                if let Some(scores) = kernel.scores_to_save() {
+                    scores.save(&mut Cmos);
+                }
+            }
         }
     }
-}
 
-fn tick() {
-    TICKS.fetch_add(1);
-}
+    fn tick() {
+        TICKS.fetch_add(1);
+    }
 
-fn key(key: DecodedKey) {
-    LAST_KEY.store(Some(key));
-}
+    fn key(key: DecodedKey) {
+        KEYS.push(key);
+    }
 
-fn startup() {
-    clear_screen();
-}
\ No newline at end of file
+    fn startup() {
+        clear_screen();
+        SAVED_SCORES.store(Some(ScoreTable::load(&mut Cmos)));
+    }
+}
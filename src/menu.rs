@@ -0,0 +1,46 @@
+use crate::platform::{ColorCode, Color};
+use crate::canvas::Canvas;
+use crate::controls::Action;
+
+/// A vertical list of choices with one highlighted. Used by the title, pause and settings screens.
+#[derive(Copy, Clone)]
+pub struct Menu {
+    items: &'static [&'static str],
+    selected: usize,
+}
+
+impl Menu {
+    pub fn new(items: &'static [&'static str]) -> Self {
+        Self {items, selected: 0}
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Moves the highlight on MoveUp and MoveDown, wrapping at either end.
+    /// Returns the highlighted item when Confirm or Fire is pressed.
+    pub fn action(&mut self, action: Action) -> Option<usize> {
+        let len = self.items.len();
+        match action {
+            Action::MoveUp => self.selected = (self.selected + len - 1) % len,
+            Action::MoveDown => self.selected = (self.selected + 1) % len,
+            Action::Confirm | Action::Fire => return Some(self.selected),
+            _ => {}
+        }
+        None
+    }
+
+    /// Draws one item per row starting at the given cell. Returns the row after the last item.
+    pub fn draw<C: Canvas>(&self, canvas: &mut C, col: usize, row: usize) -> usize {
+        for (i, item) in self.items.iter().enumerate() {
+            let color = if i == self.selected {
+                ColorCode::new(Color::Black, Color::LightGray)
+            } else {
+                ColorCode::new(Color::White, Color::Black)
+            };
+            canvas.plot_str(item, col, row + i, color);
+        }
+        row + self.items.len()
+    }
+}
@@ -0,0 +1,648 @@
+use crate::controls::Action;
+use crate::link::SerialLink;
+
+// Two kernels play one game in lockstep: each runs the whole simulation and they swap only
+// their players' inputs. A frame's inputs are sent INPUT_DELAY frames ahead of when they are
+// used, and a kernel doesn't simulate a frame until it has the other side's inputs for it.
+// Every HASH_INTERVAL frames both sides send a hash of their game state, and any difference
+// ends the match.
+//
+// Every message is framed as
+//
+//   0xA5  kind  length  payload (up to 16 bytes)  Fletcher-16 of kind, length and payload
+//
+// Text from `serial_println!` shares the wire, but it is plain ASCII, so it never contains the
+// sync byte and is skipped along with anything that fails its checksum.
+
+pub const VERSION: u8 = 1;
+pub const INPUT_DELAY: u32 = 3;
+pub const HASH_INTERVAL: u32 = 30;
+
+const SYNC: u8 = 0xA5;
+const MAX_PAYLOAD: usize = 16;
+const MAX_FRAME: usize = MAX_PAYLOAD + 5;
+const MAX_ACTIONS: usize = 4;
+const RING: usize = 16;
+const HASHES: usize = 4;
+const HELLO_EVERY: u32 = 18;
+const RESEND_AFTER: u32 = 9;
+const OUT_SIZE: usize = 512;
+
+const HELLO: u8 = 1;
+const READY: u8 = 2;
+const INPUT: u8 = 3;
+const HASH: u8 = 4;
+const BYE: u8 = 5;
+
+// The only actions that cross the link; everything else stays on the kernel it was pressed on.
+const LINKED_ACTIONS: [Action; 5] = [Action::MoveUp, Action::MoveDown, Action::MoveLeft, Action::MoveRight, Action::Fire];
+
+/// What each side offers when connecting. The side with the higher nonce becomes player 1
+/// and its seed, level and lives are used for the match.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Hello {
+    pub version: u8,
+    pub nonce: u32,
+    pub seed: u32,
+    pub level: u8,
+    pub lives: u8,
+}
+
+/// The moves and shots one player made during one frame.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Inputs {
+    actions: [u8; MAX_ACTIONS],
+    count: usize,
+}
+
+impl Inputs {
+    pub fn new() -> Self {
+        Self {actions: [0; MAX_ACTIONS], count: 0}
+    }
+
+    /// Returns false if the action can't be sent, or if the frame already holds as many as fit.
+    pub fn push(&mut self, action: Action) -> bool {
+        match LINKED_ACTIONS.iter().position(|a| *a == action) {
+            Some(code) if self.count < MAX_ACTIONS => {
+                self.actions[self.count] = code as u8;
+                self.count += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn actions(&self) -> impl Iterator<Item = Action> + '_ {
+        self.actions[..self.count].iter().map(|code| LINKED_ACTIONS[*code as usize])
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Message {
+    Hello(Hello),
+    Ready(u32),
+    Input(u32, Inputs),
+    Hash(u32, u32),
+    Bye,
+}
+
+impl Message {
+    fn encode(&self, payload: &mut [u8; MAX_PAYLOAD]) -> (u8, usize) {
+        match self {
+            Message::Hello(hello) => {
+                payload[0] = hello.version;
+                payload[1..5].copy_from_slice(&hello.nonce.to_le_bytes());
+                payload[5..9].copy_from_slice(&hello.seed.to_le_bytes());
+                payload[9] = hello.level;
+                payload[10] = hello.lives;
+                (HELLO, 11)
+            }
+            Message::Ready(nonce) => {
+                payload[..4].copy_from_slice(&nonce.to_le_bytes());
+                (READY, 4)
+            }
+            Message::Input(frame, inputs) => {
+                payload[..4].copy_from_slice(&frame.to_le_bytes());
+                payload[4..4 + inputs.count].copy_from_slice(&inputs.actions[..inputs.count]);
+                (INPUT, 4 + inputs.count)
+            }
+            Message::Hash(frame, hash) => {
+                payload[..4].copy_from_slice(&frame.to_le_bytes());
+                payload[4..8].copy_from_slice(&hash.to_le_bytes());
+                (HASH, 8)
+            }
+            Message::Bye => (BYE, 0),
+        }
+    }
+
+    fn decode(kind: u8, payload: &[u8]) -> Option<Self> {
+        let word = |i: usize| u32::from_le_bytes([payload[i], payload[i + 1], payload[i + 2], payload[i + 3]]);
+        match (kind, payload.len()) {
+            (HELLO, 11) => Some(Message::Hello(Hello {version: payload[0], nonce: word(1), seed: word(5),
+                level: payload[9], lives: payload[10]})),
+            (READY, 4) => Some(Message::Ready(word(0))),
+            (INPUT, len) if (4..=4 + MAX_ACTIONS).contains(&len) => {
+                let mut inputs = Inputs::new();
+                for code in &payload[4..] {
+                    inputs.push(*LINKED_ACTIONS.get(*code as usize)?);
+                }
+                Some(Message::Input(word(0), inputs))
+            }
+            (HASH, 8) => Some(Message::Hash(word(0), word(4))),
+            (BYE, 0) => Some(Message::Bye),
+            _ => None,
+        }
+    }
+}
+
+/// The Fletcher-16 checksum. Also used for replays sent over the link.
+pub fn fletcher16<'a>(bytes: impl IntoIterator<Item = &'a u8>) -> u16 {
+    let (mut a, mut b) = (0u16, 0u16);
+    for byte in bytes {
+        a = (a + *byte as u16) % 255;
+        b = (b + a) % 255;
+    }
+    (b << 8) | a
+}
+
+/// Rebuilds messages from the bytes coming off the link, one byte at a time.
+#[derive(Copy, Clone)]
+pub struct Decoder {
+    frame: [u8; MAX_FRAME],
+    len: usize,
+    errors: usize,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self {frame: [0; MAX_FRAME], len: 0, errors: 0}
+    }
+
+    /// How many frames have been thrown away for a bad checksum or an unknown message.
+    pub fn errors(&self) -> usize {
+        self.errors
+    }
+
+    /// Takes the next byte. Returns the first message it completes; call `poll` for any others.
+    pub fn push(&mut self, byte: u8) -> Option<Message> {
+        if self.len == 0 && byte != SYNC {
+            return None;
+        }
+        self.frame[self.len] = byte;
+        self.len += 1;
+        self.poll()
+    }
+
+    /// Takes the next complete message out of the bytes already received. A bad frame is
+    /// dropped from its sync byte only, and the rest is searched again for the next one, so a
+    /// damaged length can't swallow a good frame that follows it.
+    pub fn poll(&mut self) -> Option<Message> {
+        while self.len >= 3 {
+            let len = self.frame[2] as usize;
+            if len <= MAX_PAYLOAD {
+                if self.len < 5 + len {
+                    return None;
+                }
+                let end = 3 + len;
+                let sum = u16::from_le_bytes([self.frame[end], self.frame[end + 1]]);
+                let message = if fletcher16(&self.frame[1..end]) == sum {
+                    Message::decode(self.frame[1], &self.frame[3..end])
+                } else {
+                    None
+                };
+                if let Some(message) = message {
+                    self.consume(end + 2);
+                    return Some(message);
+                }
+            }
+            self.errors += 1;
+            let skip = self.frame[1..self.len].iter().position(|b| *b == SYNC).map_or(self.len, |i| i + 1);
+            self.consume(skip);
+        }
+        None
+    }
+
+    // Drops the first n bytes of the frame buffer.
+    fn consume(&mut self, n: usize) {
+        self.frame.copy_within(n..self.len, 0);
+        self.len -= n;
+    }
+}
+
+/// Turns a message into the bytes that go over the link.
+pub fn encode(message: &Message, out: &mut [u8; MAX_FRAME]) -> usize {
+    let mut payload = [0; MAX_PAYLOAD];
+    let (kind, len) = message.encode(&mut payload);
+    out[0] = SYNC;
+    out[1] = kind;
+    out[2] = len as u8;
+    out[3..3 + len].copy_from_slice(&payload[..len]);
+    let sum = fletcher16(&out[1..3 + len]).to_le_bytes();
+    out[3 + len] = sum[0];
+    out[4 + len] = sum[1];
+    len + 5
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum LinkState {
+    Off,
+    Connecting,
+    Playing,
+    Ended(LinkEnd),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum LinkEnd {
+    PeerLeft,
+    Desync(u32),
+}
+
+impl LinkEnd {
+    pub fn message(&self) -> &'static str {
+        match self {
+            LinkEnd::PeerLeft => "The other player left the match",
+            LinkEnd::Desync(_) => "The two games went out of sync",
+        }
+    }
+}
+
+/// Something the game has to act on after the session has read from the link.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum LinkEvent {
+    /// Both sides have agreed to play, using the settings in this hello.
+    Start(Hello),
+    Ended(LinkEnd),
+}
+
+/// One side of a linked match: the handshake, the input exchange and the hash checks.
+/// Outgoing bytes are queued here and written out by `flush`.
+#[derive(Copy, Clone)]
+pub struct Session {
+    state: LinkState,
+    hello: Hello,
+    // Where nonces come from, both the first one and any picked again after a tie.
+    next_nonce: fn() -> u32,
+    peer: Option<Hello>,
+    acked: bool,
+    local_player: usize,
+    frame: u32,
+    local: [Inputs; RING],
+    remote: [(u32, Inputs); RING],
+    pending: Inputs,
+    hashes: [(u32, u32); HASHES],
+    peer_hashes: [(u32, u32); HASHES],
+    ticks: u32,
+    waited: u32,
+    decoder: Decoder,
+    out: [u8; OUT_SIZE],
+    out_len: usize,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self {state: LinkState::Off, hello: Hello {version: VERSION, nonce: 0, seed: 0, level: 0, lives: 0},
+            next_nonce: nonce, peer: None, acked: false, local_player: 0, frame: 0, local: [Inputs::new(); RING],
+            remote: [(u32::MAX, Inputs::new()); RING], pending: Inputs::new(),
+            hashes: [(u32::MAX, 0); HASHES], peer_hashes: [(u32::MAX, 0); HASHES], ticks: 0, waited: 0,
+            decoder: Decoder::new(), out: [0; OUT_SIZE], out_len: 0}
+    }
+
+    pub fn state(&self) -> LinkState {
+        self.state
+    }
+
+    /// Which player this kernel controls, counting from 0.
+    pub fn local_player(&self) -> usize {
+        self.local_player
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// How many ticks the current frame has been held up waiting for the other side.
+    pub fn waited(&self) -> u32 {
+        self.waited
+    }
+
+    pub fn errors(&self) -> usize {
+        self.decoder.errors()
+    }
+
+    /// Starts looking for the other side, offering these settings for the match.
+    pub fn connect(&mut self, seed: u32, level: u8, lives: u8) {
+        self.connect_with(nonce, seed, level, lives);
+    }
+
+    /// Like `connect`, but draws nonces from `next_nonce` instead of the clock.
+    pub fn connect_with(&mut self, next_nonce: fn() -> u32, seed: u32, level: u8, lives: u8) {
+        let out = (self.out, self.out_len);
+        *self = Self::new();
+        (self.out, self.out_len) = out;
+        self.next_nonce = next_nonce;
+        self.hello = Hello {version: VERSION, nonce: next_nonce(), seed, level, lives};
+        self.state = LinkState::Connecting;
+        self.send(&Message::Hello(self.hello));
+    }
+
+    /// Tells the other side this one is leaving, and stops.
+    pub fn leave(&mut self) {
+        if matches!(self.state, LinkState::Connecting | LinkState::Playing) {
+            self.send(&Message::Bye);
+        }
+        self.state = LinkState::Off;
+    }
+
+    /// Called once per timer tick while connecting, or while a frame is held up.
+    /// Repeats whatever the other side may have missed.
+    pub fn tick(&mut self) {
+        self.ticks += 1;
+        match self.state {
+            LinkState::Connecting if self.ticks.is_multiple_of(HELLO_EVERY) => self.send(&Message::Hello(self.hello)),
+            LinkState::Playing => {
+                self.waited += 1;
+                if self.waited.is_multiple_of(RESEND_AFTER) {
+                    for frame in self.frame.max(INPUT_DELAY)..self.frame + INPUT_DELAY {
+                        self.send(&Message::Input(frame, self.local[frame as usize % RING]));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Adds a local action to the inputs for the next frame that hasn't been sent yet.
+    pub fn queue(&mut self, action: Action) {
+        self.pending.push(action);
+    }
+
+    /// Both players' inputs for the current frame, once the other side's have arrived.
+    pub fn inputs(&self) -> Option<[Inputs; 2]> {
+        if self.state != LinkState::Playing {
+            return None;
+        }
+        let local = self.local[self.frame as usize % RING];
+        let remote = if self.frame < INPUT_DELAY {
+            Inputs::new()
+        } else {
+            let (frame, inputs) = self.remote[self.frame as usize % RING];
+            if frame != self.frame {
+                return None;
+            }
+            inputs
+        };
+        Some(if self.local_player == 0 {[local, remote]} else {[remote, local]})
+    }
+
+    /// Moves on to the next frame once the current one has been simulated, sending the local
+    /// inputs gathered meanwhile for the frame INPUT_DELAY ahead.
+    pub fn advance(&mut self) {
+        let frame = self.frame + INPUT_DELAY;
+        self.local[frame as usize % RING] = self.pending;
+        self.send(&Message::Input(frame, self.pending));
+        self.pending = Inputs::new();
+        self.frame += 1;
+        self.waited = 0;
+    }
+
+    /// Records this side's state hash for a frame and sends it across.
+    pub fn record_hash(&mut self, frame: u32, hash: u32) -> Option<LinkEvent> {
+        self.hashes[(frame / HASH_INTERVAL) as usize % HASHES] = (frame, hash);
+        self.send(&Message::Hash(frame, hash));
+        self.compare_hashes(frame)
+    }
+
+    fn compare_hashes(&mut self, frame: u32) -> Option<LinkEvent> {
+        let slot = (frame / HASH_INTERVAL) as usize % HASHES;
+        let (mine, theirs) = (self.hashes[slot], self.peer_hashes[slot]);
+        if self.state == LinkState::Playing && mine.0 == frame && theirs.0 == frame && mine.1 != theirs.1 {
+            self.state = LinkState::Ended(LinkEnd::Desync(frame));
+            return Some(LinkEvent::Ended(LinkEnd::Desync(frame)));
+        }
+        None
+    }
+
+    /// Feeds one received byte through the decoder and handles any messages it completes.
+    /// If more than one of them needs acting on, the last is returned.
+    pub fn receive(&mut self, byte: u8) -> Option<LinkEvent> {
+        let mut event = None;
+        let mut message = self.decoder.push(byte);
+        while let Some(m) = message {
+            event = self.handle(m).or(event);
+            message = self.decoder.poll();
+        }
+        event
+    }
+
+    fn handle(&mut self, message: Message) -> Option<LinkEvent> {
+        if matches!(self.state, LinkState::Off | LinkState::Ended(_)) {
+            return None;
+        }
+        match message {
+            Message::Hello(hello) if hello.version == VERSION => {
+                self.send(&Message::Ready(hello.nonce));
+                if self.state == LinkState::Connecting {
+                    if hello.nonce == self.hello.nonce {
+                        // Both sides picked the same nonce, so neither could be player 1. Pick again
+                        // from the source; anything computed from the old nonce would tie again.
+                        self.hello.nonce = (self.next_nonce)();
+                        self.acked = false;
+                        self.send(&Message::Hello(self.hello));
+                    } else {
+                        self.peer = Some(hello);
+                    }
+                }
+            }
+            Message::Hello(_) => {}
+            Message::Ready(nonce) => self.acked |= nonce == self.hello.nonce,
+            Message::Input(frame, inputs) => {
+                if frame + (RING as u32) / 2 >= self.frame {
+                    self.remote[frame as usize % RING] = (frame, inputs);
+                }
+            }
+            Message::Hash(frame, hash) => {
+                self.peer_hashes[(frame / HASH_INTERVAL) as usize % HASHES] = (frame, hash);
+                return self.compare_hashes(frame);
+            }
+            Message::Bye => {
+                self.state = LinkState::Ended(LinkEnd::PeerLeft);
+                return Some(LinkEvent::Ended(LinkEnd::PeerLeft));
+            }
+        }
+        match (self.state, self.peer) {
+            (LinkState::Connecting, Some(peer)) if self.acked => {
+                self.state = LinkState::Playing;
+                self.local_player = if self.hello.nonce > peer.nonce {0} else {1};
+                Some(LinkEvent::Start(if self.local_player == 0 {self.hello} else {peer}))
+            }
+            _ => None,
+        }
+    }
+
+    fn send(&mut self, message: &Message) {
+        let mut frame = [0; MAX_FRAME];
+        let len = encode(message, &mut frame);
+        if self.out_len + len <= OUT_SIZE {
+            self.out[self.out_len..self.out_len + len].copy_from_slice(&frame[..len]);
+            self.out_len += len;
+        }
+    }
+
+    /// Writes out everything queued to send.
+    pub fn flush<L: SerialLink>(&mut self, link: &mut L) {
+        for byte in &self.out[..self.out_len] {
+            link.send(*byte);
+        }
+        self.out_len = 0;
+    }
+}
+
+/// A nonce that differs between two kernels booted at different moments.
+pub fn nonce() -> u32 {
+    let tsc = unsafe { core::arch::x86_64::_rdtsc() };
+    (tsc ^ (tsc >> 32)) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+    use crate::link::RamLink;
+
+    fn frame(message: &Message) -> ([u8; MAX_FRAME], usize) {
+        let mut out = [0; MAX_FRAME];
+        let len = encode(message, &mut out);
+        (out, len)
+    }
+
+    fn decode_all(decoder: &mut Decoder, bytes: &[u8]) -> Vec<Message> {
+        let mut messages = Vec::new();
+        for byte in bytes {
+            let mut message = decoder.push(*byte);
+            while let Some(m) = message {
+                messages.push(m);
+                message = decoder.poll();
+            }
+        }
+        messages
+    }
+
+    // Carries everything each side has queued across, and feeds it in.
+    fn pump(a: &mut Session, b: &mut Session, la: &mut RamLink, lb: &mut RamLink) -> [Option<LinkEvent>; 2] {
+        a.flush(la);
+        b.flush(lb);
+        la.transfer(lb);
+        lb.transfer(la);
+        [deliver(a, la), deliver(b, lb)]
+    }
+
+    fn deliver(session: &mut Session, link: &mut RamLink) -> Option<LinkEvent> {
+        let mut event = None;
+        while let Some(byte) = link.receive() {
+            event = session.receive(byte).or(event);
+        }
+        event
+    }
+
+    fn connected(nonces: [fn() -> u32; 2]) -> (Session, Session, RamLink, RamLink) {
+        let (mut a, mut b) = (Session::new(), Session::new());
+        let (mut la, mut lb) = (RamLink::new(), RamLink::new());
+        a.connect_with(nonces[0], 11, 1, 3);
+        b.connect_with(nonces[1], 22, 2, 5);
+        for _ in 0..10 {
+            a.tick();
+            b.tick();
+            pump(&mut a, &mut b, &mut la, &mut lb);
+        }
+        assert_eq!((a.state(), b.state()), (LinkState::Playing, LinkState::Playing));
+        (a, b, la, lb)
+    }
+
+    #[test]
+    fn skips_noise_and_bad_frames() {
+        let mut inputs = Inputs::new();
+        assert!(inputs.push(Action::Fire));
+        assert!(inputs.push(Action::MoveLeft));
+        assert!(!inputs.push(Action::Pause));
+        let message = Message::Input(77, inputs);
+        let (bytes, len) = frame(&message);
+        let mut decoder = Decoder::new();
+        let noisy: Vec<u8> = b"score 120\n".iter().chain(&bytes[..len]).copied().collect();
+        assert_eq!(decode_all(&mut decoder, &noisy), [message]);
+        let mut damaged = bytes;
+        damaged[4] ^= 1;
+        assert_eq!(decode_all(&mut decoder, &damaged[..len]), []);
+        assert_eq!(decoder.errors(), 1);
+    }
+
+    #[test]
+    fn finds_a_good_frame_after_a_damaged_length() {
+        let (first, first_len) = frame(&Message::Hash(30, 0xDEAD_BEEF));
+        let (second, second_len) = frame(&Message::Ready(9));
+        let (third, third_len) = frame(&Message::Bye);
+        let mut bytes: Vec<u8> = first[..first_len].to_vec();
+        bytes[2] = MAX_PAYLOAD as u8;
+        bytes.extend_from_slice(&second[..second_len]);
+        bytes.extend_from_slice(&third[..third_len]);
+        let mut decoder = Decoder::new();
+        assert_eq!(decode_all(&mut decoder, &bytes), [Message::Ready(9), Message::Bye]);
+        assert_eq!(decoder.errors(), 1);
+    }
+
+    #[test]
+    fn handshake_picks_the_higher_nonce_as_host() {
+        let (mut a, mut b) = (Session::new(), Session::new());
+        let (mut la, mut lb) = (RamLink::new(), RamLink::new());
+        a.connect_with(|| 5, 11, 1, 3);
+        b.connect_with(|| 9, 22, 2, 5);
+        let [ea, eb] = pump(&mut a, &mut b, &mut la, &mut lb);
+        let [ea2, eb2] = pump(&mut a, &mut b, &mut la, &mut lb);
+        let host = Hello {version: VERSION, nonce: 9, seed: 22, level: 2, lives: 5};
+        assert_eq!(ea.or(ea2), Some(LinkEvent::Start(host)));
+        assert_eq!(eb.or(eb2), Some(LinkEvent::Start(host)));
+        assert_eq!((a.local_player(), b.local_player()), (1, 0));
+    }
+
+    // Each side's nonces, handed out in turn, and how many each side has drawn.
+    const NONCES: [[u32; 2]; 2] = [[42, 7], [42, 8]];
+    thread_local! {
+        static DRAWN: Cell<[usize; 2]> = const { Cell::new([0; 2]) };
+    }
+
+    fn draw(side: usize) -> u32 {
+        DRAWN.with(|drawn| {
+            let mut counts = drawn.get();
+            let nonce = NONCES[side][counts[side]];
+            counts[side] += 1;
+            drawn.set(counts);
+            nonce
+        })
+    }
+
+    #[test]
+    fn tied_nonces_are_rerolled() {
+        let (a, b, _, _) = connected([|| draw(0), || draw(1)]);
+        assert_eq!(DRAWN.with(Cell::get), [2, 2]);
+        assert_eq!((a.hello.nonce, b.hello.nonce), (7, 8));
+        assert_eq!((a.local_player(), b.local_player()), (1, 0));
+    }
+
+    #[test]
+    fn exchanges_inputs_in_lockstep() {
+        let (mut a, mut b, mut la, mut lb) = connected([|| 1, || 2]);
+        let moves = [Action::MoveUp, Action::Fire, Action::MoveLeft];
+        for frame in 0..40u32 {
+            a.queue(moves[frame as usize % moves.len()]);
+            b.queue(Action::MoveDown);
+            pump(&mut a, &mut b, &mut la, &mut lb);
+            let (ia, ib) = (a.inputs().unwrap(), b.inputs().unwrap());
+            assert_eq!(ia, ib);
+            if frame >= INPUT_DELAY {
+                let sent = moves[(frame - INPUT_DELAY) as usize % moves.len()];
+                assert_eq!(ia[a.local_player()].actions().collect::<Vec<_>>(), [sent]);
+                assert_eq!(ia[b.local_player()].actions().collect::<Vec<_>>(), [Action::MoveDown]);
+            }
+            a.advance();
+            b.advance();
+        }
+        // b's last inputs are still on the wire, so a can only run ahead so far before it waits.
+        for _ in 1..INPUT_DELAY {
+            assert!(a.inputs().is_some());
+            a.advance();
+        }
+        assert_eq!(a.inputs(), None);
+    }
+
+    #[test]
+    fn detects_a_hash_mismatch() {
+        let (mut a, mut b, mut la, mut lb) = connected([|| 1, || 2]);
+        assert_eq!(a.record_hash(HASH_INTERVAL, 100), None);
+        assert_eq!(b.record_hash(HASH_INTERVAL, 100), None);
+        assert_eq!(pump(&mut a, &mut b, &mut la, &mut lb), [None, None]);
+        assert_eq!(a.record_hash(2 * HASH_INTERVAL, 100), None);
+        assert_eq!(b.record_hash(2 * HASH_INTERVAL, 101), None);
+        let ended = Some(LinkEvent::Ended(LinkEnd::Desync(2 * HASH_INTERVAL)));
+        assert_eq!(pump(&mut a, &mut b, &mut la, &mut lb), [ended, ended]);
+        assert_eq!(a.state(), LinkState::Ended(LinkEnd::Desync(2 * HASH_INTERVAL)));
+    }
+}
@@ -0,0 +1,92 @@
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+
+// CMOS registers 0x00-0x3F hold the clock and the BIOS configuration. QEMU and SeaBIOS also
+// use a few bytes above that (0x5B-0x5F for memory size and CPU count), which leaves these two
+// runs free for the game.
+const SPARE: [(u8, usize); 2] = [(0x40, 27), (0x60, 32)];
+
+/// Setting bit 7 of the index keeps NMIs masked while a CMOS register is selected.
+const NMI_DISABLE: u8 = 0x80;
+
+/// A small block of memory that survives a reset. Addresses count from 0 up to `size()`.
+pub trait Nvram {
+    fn size(&self) -> usize;
+    fn read(&mut self, addr: usize) -> u8;
+    fn write(&mut self, addr: usize, value: u8);
+}
+
+/// The spare bytes of the CMOS RAM beside the real-time clock, reached through ports 0x70 and 0x71.
+pub struct Cmos;
+
+impl Cmos {
+    fn register(addr: usize) -> u8 {
+        let mut addr = addr;
+        for (start, len) in SPARE {
+            if addr < len {
+                return start + addr as u8;
+            }
+            addr -= len;
+        }
+        panic!("CMOS address out of range");
+    }
+
+    /// Reads any CMOS register, including the clock's.
+    pub fn read_register(register: u8) -> u8 {
+        let mut index: Port<u8> = Port::new(0x70);
+        let mut data: Port<u8> = Port::new(0x71);
+        interrupts::without_interrupts(|| unsafe {
+            index.write(NMI_DISABLE | register);
+            data.read()
+        })
+    }
+
+    fn write_register(register: u8, value: u8) {
+        let mut index: Port<u8> = Port::new(0x70);
+        let mut data: Port<u8> = Port::new(0x71);
+        interrupts::without_interrupts(|| unsafe {
+            index.write(NMI_DISABLE | register);
+            data.write(value);
+        })
+    }
+}
+
+impl Nvram for Cmos {
+    fn size(&self) -> usize {
+        SPARE.iter().map(|(_, len)| len).sum()
+    }
+
+    fn read(&mut self, addr: usize) -> u8 {
+        Self::read_register(Self::register(addr))
+    }
+
+    fn write(&mut self, addr: usize, value: u8) {
+        Self::write_register(Self::register(addr), value)
+    }
+}
+
+/// 64 bytes of plain memory that the score table can be saved to in place of the CMOS.
+#[derive(Copy, Clone)]
+pub struct RamNvram {
+    bytes: [u8; 64],
+}
+
+impl RamNvram {
+    pub fn new() -> Self {
+        Self {bytes: [0; 64]}
+    }
+}
+
+impl Nvram for RamNvram {
+    fn size(&self) -> usize {
+        self.bytes.len()
+    }
+
+    fn read(&mut self, addr: usize) -> u8 {
+        self.bytes[addr]
+    }
+
+    fn write(&mut self, addr: usize, value: u8) {
+        self.bytes[addr] = value;
+    }
+}
@@ -0,0 +1,80 @@
+// The few pieces of `pluggable_interrupt_os` that the game itself uses. That crate brings its
+// own panic handler, so it is only linked into the kernel; everywhere else, such as under
+// `cargo test`, these stand-ins take its place and the game runs on the host.
+
+#[cfg(target_os = "none")]
+pub use pluggable_interrupt_os::vga_buffer::{BUFFER_WIDTH, BUFFER_HEIGHT, ColorCode, Color, plot};
+#[cfg(target_os = "none")]
+pub use pluggable_interrupt_os::{serial_print, serial_println};
+
+#[cfg(not(target_os = "none"))]
+pub use host::*;
+
+#[cfg(not(target_os = "none"))]
+mod host {
+    pub const BUFFER_WIDTH: usize = 80;
+    pub const BUFFER_HEIGHT: usize = 25;
+
+    /// The 16 VGA text colors.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[repr(u8)]
+    pub enum Color {
+        Black = 0,
+        Blue = 1,
+        Green = 2,
+        Cyan = 3,
+        Red = 4,
+        Magenta = 5,
+        Brown = 6,
+        LightGray = 7,
+        DarkGray = 8,
+        LightBlue = 9,
+        LightGreen = 10,
+        LightCyan = 11,
+        LightRed = 12,
+        Pink = 13,
+        Yellow = 14,
+        White = 15,
+    }
+
+    const COLORS: [Color; 16] = [Color::Black, Color::Blue, Color::Green, Color::Cyan, Color::Red, Color::Magenta,
+        Color::Brown, Color::LightGray, Color::DarkGray, Color::LightBlue, Color::LightGreen, Color::LightCyan,
+        Color::LightRed, Color::Pink, Color::Yellow, Color::White];
+
+    /// A foreground and background color, packed as in the VGA text buffer.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ColorCode(u8);
+
+    impl ColorCode {
+        pub fn new(foreground: Color, background: Color) -> ColorCode {
+            ColorCode((background as u8) << 4 | (foreground as u8))
+        }
+
+        pub fn foreground(&self) -> Color {
+            COLORS[(self.0 & 0xF) as usize]
+        }
+
+        pub fn background(&self) -> Color {
+            COLORS[(self.0 >> 4) as usize]
+        }
+    }
+
+    /// There is no screen off the kernel.
+    pub fn plot(_c: char, _col: usize, _row: usize, _color: ColorCode) {}
+
+    /// There is no serial port off the kernel either, so serial output is dropped.
+    macro_rules! serial_print {
+        ($($arg:tt)*) => {{
+            let _ = format_args!($($arg)*);
+        }};
+    }
+
+    macro_rules! serial_println {
+        ($($arg:tt)*) => {{
+            let _ = format_args!($($arg)*);
+        }};
+    }
+
+    pub(crate) use serial_print;
+    pub(crate) use serial_println;
+}
@@ -0,0 +1,220 @@
+use crate::{Projectile, Shooter};
+
+/// Something that can sit in a `Pool`. A removed item stops being live, and the pool takes
+/// its slot back the next time it sweeps.
+pub trait Pooled: Copy {
+    fn empty() -> Self;
+    fn is_live(&self) -> bool;
+    fn remove(&mut self);
+}
+
+impl Pooled for Projectile {
+    fn empty() -> Self {
+        Projectile::new()
+    }
+
+    fn is_live(&self) -> bool {
+        Projectile::is_live(self)
+    }
+
+    fn remove(&mut self) {
+        Projectile::remove(self)
+    }
+}
+
+impl Pooled for Shooter {
+    fn empty() -> Self {
+        Shooter::new()
+    }
+
+    fn is_live(&self) -> bool {
+        Shooter::is_live(self)
+    }
+
+    fn remove(&mut self) {
+        Shooter::remove(self)
+    }
+}
+
+/// Enemy projectiles in flight.
+pub type ProjectilePool<const N: usize> = Pool<Projectile, N>;
+
+/// The shooters in the arena. N is the most that can be out at once.
+pub type ShooterRegistry<const N: usize> = Pool<Shooter, N>;
+
+/// How full a pool is, and how often it has been.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct PoolStats {
+    pub capacity: usize,
+    pub live: usize,
+    /// The most that have been live at once.
+    pub peak: usize,
+    /// Spawns turned away because every slot was taken.
+    pub dropped: usize,
+}
+
+/// A fixed set of slots. Free slots are kept on a stack, so spawning never has to search, and
+/// the slots in use are kept in a dense list so only live items are visited. Each item keeps
+/// the slot it was given until it is despawned, so a slot number can be held on to.
+#[derive(Copy, Clone)]
+pub struct Pool<T: Pooled, const N: usize> {
+    slots: [T; N],
+    free: [u16; N],
+    free_len: usize,
+    active: [u16; N],
+    active_len: usize,
+    // Where each slot in use sits in `active`.
+    position: [u16; N],
+    peak: usize,
+    dropped: usize,
+}
+
+impl<T: Pooled, const N: usize> Pool<T, N> {
+    pub fn new() -> Self {
+        let mut free = [0; N];
+        // Hand out the lowest slots first.
+        for (i, slot) in free.iter_mut().enumerate() {
+            *slot = (N - 1 - i) as u16;
+        }
+        Self {slots: [T::empty(); N], free, free_len: N, active: [0; N], active_len: 0, position: [0; N],
+            peak: 0, dropped: 0}
+    }
+
+    /// Adds an item and returns its slot. When the pool is full the new item is dropped and
+    /// counted, and everything already in it carries on.
+    pub fn spawn(&mut self, item: T) -> Option<usize> {
+        if !item.is_live() {
+            return None;
+        }
+        if self.free_len == 0 {
+            self.dropped += 1;
+            return None;
+        }
+        self.free_len -= 1;
+        let slot = self.free[self.free_len];
+        self.slots[slot as usize] = item;
+        self.position[slot as usize] = self.active_len as u16;
+        self.active[self.active_len] = slot;
+        self.active_len += 1;
+        self.peak = self.peak.max(self.active_len);
+        Some(slot as usize)
+    }
+
+    /// Removes the item in a slot and frees the slot. Freeing a slot twice does nothing.
+    pub fn despawn(&mut self, slot: usize) {
+        let i = self.position[slot] as usize;
+        if i >= self.active_len || self.active[i] as usize != slot {
+            return;
+        }
+        self.slots[slot].remove();
+        self.active_len -= 1;
+        let last = self.active[self.active_len];
+        self.active[i] = last;
+        self.position[last as usize] = i as u16;
+        self.free[self.free_len] = slot as u16;
+        self.free_len += 1;
+    }
+
+    pub fn get(&self, slot: usize) -> &T {
+        &self.slots[slot]
+    }
+
+    /// How many items are live.
+    pub fn len(&self) -> usize {
+        self.active_len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.active_len == 0
+    }
+
+    /// The slot of the i-th live item, for loops that need the rest of the game while they
+    /// change each item. Despawning during such a loop reorders the items after it.
+    pub fn slot(&self, i: usize) -> usize {
+        self.active[i] as usize
+    }
+
+    pub fn get_mut(&mut self, slot: usize) -> &mut T {
+        &mut self.slots[slot]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        self.active[..self.active_len].iter().map(|slot| &self.slots[*slot as usize])
+    }
+
+    /// The slot of the first live item that matches.
+    pub fn find<F: Fn(&T) -> bool>(&self, f: F) -> Option<usize> {
+        self.active[..self.active_len].iter().map(|slot| *slot as usize).find(|slot| f(&self.slots[*slot]))
+    }
+
+    /// Runs `f` on every live item, then frees the slots of any it removed.
+    pub fn retain_mut<F: FnMut(&mut T)>(&mut self, mut f: F) {
+        for i in 0..self.active_len {
+            f(&mut self.slots[self.active[i] as usize]);
+        }
+        self.sweep();
+    }
+
+    /// Frees the slots of items that have been removed or have left the arena.
+    pub fn sweep(&mut self) {
+        let mut i = 0;
+        while i < self.active_len {
+            let slot = self.active[i] as usize;
+            if self.slots[slot].is_live() {
+                i += 1;
+            } else {
+                self.despawn(slot);
+            }
+        }
+    }
+
+    /// Removes everything, keeping the statistics.
+    pub fn clear(&mut self) {
+        self.retain_mut(|item| item.remove());
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {capacity: N, live: self.active_len, peak: self.peak, dropped: self.dropped}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: usize) -> Projectile {
+        let mut proj = Projectile::new();
+        proj.move_to(x, 1);
+        proj
+    }
+
+    #[test]
+    fn reuses_freed_slots() {
+        let mut pool: ProjectilePool<4> = Pool::new();
+        let slots: Vec<_> = (0..4).map(|x| pool.spawn(at(x)).unwrap()).collect();
+        assert_eq!(slots, [0, 1, 2, 3]);
+        pool.despawn(1);
+        pool.despawn(1);
+        assert_eq!(pool.len(), 3);
+        assert_eq!(pool.spawn(at(9)), Some(1));
+        assert_eq!(pool.get(1).x, 9);
+        pool.get_mut(2).remove();
+        pool.sweep();
+        assert_eq!(pool.spawn(at(8)), Some(2));
+        assert_eq!(pool.iter().map(|p| p.x).sum::<usize>(), 9 + 8 + 3);
+    }
+
+    #[test]
+    fn refuses_spawns_when_full() {
+        let mut pool: ProjectilePool<2> = Pool::new();
+        assert!(pool.spawn(at(0)).is_some());
+        assert!(pool.spawn(at(1)).is_some());
+        assert_eq!(pool.spawn(at(2)), None);
+        assert_eq!(pool.spawn(Projectile::new()), None);
+        assert_eq!(pool.stats(), PoolStats {capacity: 2, live: 2, peak: 2, dropped: 1});
+        pool.clear();
+        assert!(pool.is_empty());
+        assert_eq!(pool.spawn(at(3)), Some(1));
+        assert_eq!(pool.stats(), PoolStats {capacity: 2, live: 1, peak: 2, dropped: 1});
+    }
+}
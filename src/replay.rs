@@ -0,0 +1,397 @@
+use core::fmt;
+use crate::PlayMode;
+use crate::controls::Action;
+use crate::link::SerialLink;
+use crate::netplay::fletcher16;
+
+// A replay is sent as
+//
+//   "SJRP"  format  version (major, minor, patch)  seed (4)  level  lives  mode  length (2)
+//   events (length bytes)  Fletcher-16 of everything after the magic
+//
+// with every number little-endian. Each event is the ticks since the last event as a
+// LEB128 varint, then a byte holding the player in the high nibble and the action in the low.
+
+const MAGIC: [u8; 4] = *b"SJRP";
+const FORMAT: u8 = 1;
+const HEADER: usize = 17;
+pub const MAX_EVENT_BYTES: usize = 4096;
+const MAX_ENCODED: usize = HEADER + MAX_EVENT_BYTES + 2;
+
+// Confirm stands for carrying on past the level-complete screen.
+const ACTIONS: [Action; 6] = [Action::MoveUp, Action::MoveDown, Action::MoveLeft, Action::MoveRight, Action::Fire, Action::Confirm];
+const MODES: [PlayMode; 3] = [PlayMode::Solo, PlayMode::CoOp, PlayMode::Competitive];
+
+/// The version of this crate, which a replay must match to play back the same way.
+pub fn crate_version() -> [u8; 3] {
+    let part = |s: &str| s.parse::<u8>().unwrap_or(0);
+    [part(env!("CARGO_PKG_VERSION_MAJOR")), part(env!("CARGO_PKG_VERSION_MINOR")), part(env!("CARGO_PKG_VERSION_PATCH"))]
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ReplayError {
+    Format(u8),
+    Version([u8; 3]),
+    TooLong(usize),
+    BadHeader,
+    Checksum,
+}
+
+impl ReplayError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            ReplayError::Format(_) => "unknown replay format",
+            ReplayError::Version(_) => "recorded by a different version of the game",
+            ReplayError::TooLong(_) => "replay is too long",
+            ReplayError::BadHeader => "replay has an impossible level, lives or mode",
+            ReplayError::Checksum => "replay was damaged on the way",
+        }
+    }
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message())?;
+        match self {
+            ReplayError::Format(format) => write!(f, " ({})", format),
+            ReplayError::Version([major, minor, patch]) => write!(f, " ({}.{}.{})", major, minor, patch),
+            ReplayError::TooLong(len) => write!(f, " ({} bytes)", len),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Where playback has got to in a replay.
+#[derive(Copy, Clone)]
+pub struct Playback {
+    pos: usize,
+    tick: isize,
+}
+
+impl Playback {
+    pub fn new() -> Self {
+        Self {pos: 0, tick: 0}
+    }
+}
+
+/// One run's inputs, stamped with the tick they arrived on. Along with the settings the run
+/// started from, that is all it takes to play the run again exactly.
+#[derive(Copy, Clone)]
+pub struct Replay {
+    pub version: [u8; 3],
+    pub seed: u32,
+    pub level: usize,
+    pub lives: isize,
+    pub mode: PlayMode,
+    events: [u8; MAX_EVENT_BYTES],
+    len: usize,
+    count: usize,
+    last_tick: isize,
+}
+
+impl Replay {
+    pub fn new(seed: u32, level: usize, lives: isize, mode: PlayMode) -> Self {
+        Self {version: crate_version(), seed, level, lives, mode, events: [0; MAX_EVENT_BYTES], len: 0, count: 0,
+            last_tick: 0}
+    }
+
+    /// How many inputs have been recorded.
+    pub fn inputs(&self) -> usize {
+        self.count
+    }
+
+    /// The tick of the last input.
+    pub fn last_tick(&self) -> isize {
+        self.last_tick
+    }
+
+    /// Adds an input on the given tick, which can't be earlier than the last one.
+    /// Returns false once there is no room left, leaving the replay as it was.
+    pub fn push(&mut self, tick: isize, player: usize, action: Action) -> bool {
+        let Some(code) = ACTIONS.iter().position(|a| *a == action) else {
+            return true;
+        };
+        let mut bytes = [0u8; 6];
+        let mut n = 0;
+        let mut delta = (tick - self.last_tick).max(0) as u32;
+        loop {
+            let more = if delta >= 0x80 {0x80} else {0};
+            bytes[n] = (delta & 0x7F) as u8 | more;
+            n += 1;
+            delta >>= 7;
+            if more == 0 {
+                break;
+            }
+        }
+        bytes[n] = (player as u8) << 4 | code as u8;
+        n += 1;
+        if self.len + n > MAX_EVENT_BYTES {
+            return false;
+        }
+        self.events[self.len..self.len + n].copy_from_slice(&bytes[..n]);
+        self.len += n;
+        self.count += 1;
+        self.last_tick = self.last_tick.max(tick);
+        true
+    }
+
+    /// The next input, if it is due by `tick`. Playback stops at the first malformed event.
+    pub fn next(&self, playback: &mut Playback, tick: isize) -> Option<(usize, Action)> {
+        let mut pos = playback.pos;
+        let mut delta = 0isize;
+        let mut shift = 0;
+        loop {
+            let byte = *self.events[..self.len].get(pos)?;
+            pos += 1;
+            delta |= ((byte & 0x7F) as isize) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            if shift > 28 {
+                playback.pos = self.len;
+                return None;
+            }
+        }
+        let code = *self.events[..self.len].get(pos)?;
+        let (player, action) = ((code >> 4) as usize, ACTIONS.get((code & 0xF) as usize));
+        let Some(action) = action.filter(|_| player < 2) else {
+            playback.pos = self.len;
+            return None;
+        };
+        if playback.tick + delta > tick {
+            return None;
+        }
+        playback.pos = pos + 1;
+        playback.tick += delta;
+        Some((player, *action))
+    }
+
+    fn header(&self) -> [u8; HEADER] {
+        let mut header = [0; HEADER];
+        header[..4].copy_from_slice(&MAGIC);
+        header[4] = FORMAT;
+        header[5..8].copy_from_slice(&self.version);
+        header[8..12].copy_from_slice(&self.seed.to_le_bytes());
+        header[12] = self.level as u8;
+        header[13] = self.lives as u8;
+        header[14] = MODES.iter().position(|m| *m == self.mode).unwrap_or(0) as u8;
+        header[15..17].copy_from_slice(&(self.len as u16).to_le_bytes());
+        header
+    }
+
+    fn checksum(&self) -> u16 {
+        fletcher16(self.header()[4..].iter().chain(self.events[..self.len].iter()))
+    }
+
+    /// How many bytes the replay takes to send.
+    pub fn encoded_len(&self) -> usize {
+        HEADER + self.len + 2
+    }
+
+    /// Reads a replay as sent by `ReplayExport`.
+    pub fn decode(bytes: &[u8]) -> Result<Self, ReplayError> {
+        if bytes.len() < HEADER + 2 || bytes[..4] != MAGIC {
+            return Err(ReplayError::BadHeader);
+        }
+        if bytes[4] != FORMAT {
+            return Err(ReplayError::Format(bytes[4]));
+        }
+        let len = u16::from_le_bytes([bytes[15], bytes[16]]) as usize;
+        if len > MAX_EVENT_BYTES {
+            return Err(ReplayError::TooLong(len));
+        }
+        if bytes.len() != HEADER + len + 2 {
+            return Err(ReplayError::BadHeader);
+        }
+        let sum = u16::from_le_bytes([bytes[HEADER + len], bytes[HEADER + len + 1]]);
+        if fletcher16(&bytes[4..HEADER + len]) != sum {
+            return Err(ReplayError::Checksum);
+        }
+        let version = [bytes[5], bytes[6], bytes[7]];
+        if version != crate_version() {
+            return Err(ReplayError::Version(version));
+        }
+        let mode = *MODES.get(bytes[14] as usize).ok_or(ReplayError::BadHeader)?;
+        if bytes[13] == 0 {
+            return Err(ReplayError::BadHeader);
+        }
+        let seed = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]);
+        let mut replay = Self::new(seed, bytes[12] as usize, bytes[13] as isize, mode);
+        replay.events[..len].copy_from_slice(&bytes[HEADER..HEADER + len]);
+        replay.len = len;
+        let mut playback = Playback::new();
+        while replay.next(&mut playback, isize::MAX).is_some() {
+            replay.count += 1;
+        }
+        replay.last_tick = playback.tick;
+        Ok(replay)
+    }
+}
+
+/// Sends a replay over a link a little at a time, so the game keeps running meanwhile.
+/// The replay is encoded in full when the export starts, so a run recorded or received
+/// while it is still sending doesn't change what goes out.
+#[derive(Copy, Clone)]
+pub struct ReplayExport {
+    bytes: [u8; MAX_ENCODED],
+    len: usize,
+    pos: usize,
+}
+
+impl ReplayExport {
+    pub fn new(replay: &Replay) -> Self {
+        let mut bytes = [0; MAX_ENCODED];
+        let len = replay.encoded_len();
+        bytes[..HEADER].copy_from_slice(&replay.header());
+        bytes[HEADER..HEADER + replay.len].copy_from_slice(&replay.events[..replay.len]);
+        bytes[HEADER + replay.len..len].copy_from_slice(&replay.checksum().to_le_bytes());
+        Self {bytes, len, pos: 0}
+    }
+
+    /// Sends up to `max` more bytes. Returns true once the whole replay has gone.
+    pub fn send<L: SerialLink>(&mut self, link: &mut L, max: usize) -> bool {
+        let end = (self.pos + max).min(self.len);
+        for byte in &self.bytes[self.pos..end] {
+            link.send(*byte);
+        }
+        self.pos = end;
+        self.pos == self.len
+    }
+}
+
+/// Picks a replay out of the bytes coming off a link. Anything before the magic is skipped.
+#[derive(Copy, Clone)]
+pub struct ReplayImport {
+    bytes: [u8; MAX_ENCODED],
+    len: usize,
+}
+
+impl ReplayImport {
+    pub fn new() -> Self {
+        Self {bytes: [0; MAX_ENCODED], len: 0}
+    }
+
+    /// How many bytes of the replay have arrived so far.
+    pub fn received(&self) -> usize {
+        self.len
+    }
+
+    /// Takes the next byte. Returns the replay, or why it was rejected, once it is complete.
+    pub fn push(&mut self, byte: u8) -> Option<Result<Replay, ReplayError>> {
+        if self.len < MAGIC.len() && byte != MAGIC[self.len] {
+            self.len = if byte == MAGIC[0] {1} else {0};
+            return None;
+        }
+        self.bytes[self.len] = byte;
+        self.len += 1;
+        if self.len < HEADER {
+            return None;
+        }
+        let events = u16::from_le_bytes([self.bytes[15], self.bytes[16]]) as usize;
+        if events > MAX_EVENT_BYTES {
+            self.len = 0;
+            return Some(Err(ReplayError::TooLong(events)));
+        }
+        if self.len < HEADER + events + 2 {
+            return None;
+        }
+        let result = Replay::decode(&self.bytes[..self.len]);
+        self.len = 0;
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::RamLink;
+
+    fn recorded(inputs: isize) -> Replay {
+        let mut replay = Replay::new(7, 2, 3, PlayMode::CoOp);
+        for tick in 0..inputs {
+            assert!(replay.push(tick * 3, (tick % 2) as usize, Action::Fire));
+        }
+        replay
+    }
+
+    fn encoded(replay: &Replay) -> Vec<u8> {
+        let export = ReplayExport::new(replay);
+        export.bytes[..export.len].to_vec()
+    }
+
+    // Puts a checksum back over bytes changed on purpose, so only the change is wrong.
+    fn resum(bytes: &mut [u8]) {
+        let end = bytes.len() - 2;
+        let sum = fletcher16(&bytes[4..end]);
+        bytes[end..].copy_from_slice(&sum.to_le_bytes());
+    }
+
+    #[test]
+    fn sends_in_chunks_that_import_whole() {
+        let mut replay = recorded(200);
+        let (mut sender, mut receiver) = (RamLink::new(), RamLink::new());
+        let mut export = ReplayExport::new(&replay);
+        // What was recorded after the export started isn't sent.
+        assert!(replay.push(900, 0, Action::MoveUp));
+        let mut import = ReplayImport::new();
+        let mut result = None;
+        for _ in 0..replay.encoded_len() {
+            let done = export.send(&mut sender, 5);
+            sender.transfer(&mut receiver);
+            while let Some(byte) = receiver.receive() {
+                result = import.push(byte).or(result);
+            }
+            if done {
+                break;
+            }
+        }
+        let received = result.unwrap().unwrap();
+        assert_eq!(received.inputs(), 200);
+        assert_eq!(received.seed, 7);
+        assert_eq!(received.mode, PlayMode::CoOp);
+    }
+
+    #[test]
+    fn decodes_what_it_encodes() {
+        let replay = recorded(50);
+        let decoded = Replay::decode(&encoded(&replay)).unwrap();
+        assert_eq!((decoded.inputs(), decoded.last_tick()), (50, 147));
+        assert_eq!((decoded.seed, decoded.level, decoded.lives), (7, 2, 3));
+    }
+
+    #[test]
+    fn rejects_a_bad_magic() {
+        let mut bytes = encoded(&recorded(5));
+        bytes[0] = b'X';
+        assert_eq!(Replay::decode(&bytes).err(), Some(ReplayError::BadHeader));
+    }
+
+    #[test]
+    fn rejects_another_version() {
+        let mut bytes = encoded(&recorded(5));
+        bytes[6] = bytes[6].wrapping_add(1);
+        resum(&mut bytes);
+        let [major, minor, patch] = crate_version();
+        let version = [major, minor.wrapping_add(1), patch];
+        assert_eq!(Replay::decode(&bytes).err(), Some(ReplayError::Version(version)));
+    }
+
+    #[test]
+    fn rejects_a_bad_checksum() {
+        let mut bytes = encoded(&recorded(5));
+        bytes[HEADER + 1] ^= 0x04;
+        assert_eq!(Replay::decode(&bytes).err(), Some(ReplayError::Checksum));
+    }
+
+    #[test]
+    fn rejects_too_many_events() {
+        let mut bytes = encoded(&recorded(5));
+        let len = (MAX_EVENT_BYTES + 1) as u16;
+        bytes[15..17].copy_from_slice(&len.to_le_bytes());
+        assert_eq!(Replay::decode(&bytes).err(), Some(ReplayError::TooLong(MAX_EVENT_BYTES + 1)));
+        let mut import = ReplayImport::new();
+        let rejected = bytes.iter().find_map(|byte| import.push(*byte)).and_then(Result::err);
+        assert_eq!(rejected, Some(ReplayError::TooLong(MAX_EVENT_BYTES + 1)));
+    }
+}
@@ -0,0 +1,313 @@
+use crate::platform::{ColorCode, Color};
+use pc_keyboard::{DecodedKey, KeyCode};
+use crate::canvas::Canvas;
+use crate::nvram::Nvram;
+
+pub const MAX_SCORES: usize = 5;
+
+// Saved layout: a magic byte, the entry count, a checksum of the magic, count and entries, then
+// each entry as three initials, the score in three bytes and the seed in four, all little-endian.
+const MAGIC: u8 = 0x5A;
+const HEADER: usize = 3;
+const ENTRY: usize = 10;
+const MAX_POINTS: isize = 0xFF_FFFF;
+
+// What the arrow keys step an initial through.
+const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+/// One finished run.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Score {
+    pub initials: [u8; 3],
+    pub points: isize,
+    pub seed: u32,
+}
+
+impl Score {
+    fn to_bytes(self) -> [u8; ENTRY] {
+        let points = self.points.clamp(0, MAX_POINTS) as u32;
+        let mut bytes = [0; ENTRY];
+        bytes[..3].copy_from_slice(&self.initials);
+        bytes[3..6].copy_from_slice(&points.to_le_bytes()[..3]);
+        bytes[6..].copy_from_slice(&self.seed.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: [u8; ENTRY]) -> Self {
+        Self {initials: [bytes[0], bytes[1], bytes[2]],
+            points: u32::from_le_bytes([bytes[3], bytes[4], bytes[5], 0]) as isize,
+            seed: u32::from_le_bytes([bytes[6], bytes[7], bytes[8], bytes[9]])}
+    }
+}
+
+/// The best runs so far, highest first.
+#[derive(Copy, Clone)]
+pub struct ScoreTable {
+    entries: [Score; MAX_SCORES],
+    count: usize,
+}
+
+impl ScoreTable {
+    pub fn new() -> Self {
+        Self {entries: [Score {initials: *b"---", points: 0, seed: 0}; MAX_SCORES], count: 0}
+    }
+
+    /// Reads a table saved by `save`. Memory that was never written, or that fails its
+    /// checksum, gives an empty table rather than garbage scores.
+    pub fn load<N: Nvram>(nvram: &mut N) -> Self {
+        let mut table = Self::new();
+        if nvram.size() < HEADER + ENTRY * MAX_SCORES || nvram.read(0) != MAGIC {
+            return table;
+        }
+        let count = nvram.read(1) as usize;
+        if count > MAX_SCORES {
+            return table;
+        }
+        let mut sum = MAGIC.wrapping_add(count as u8);
+        for i in 0..count {
+            let mut bytes = [0; ENTRY];
+            for (j, byte) in bytes.iter_mut().enumerate() {
+                *byte = nvram.read(HEADER + i * ENTRY + j);
+                sum = sum.wrapping_add(*byte);
+            }
+            table.entries[i] = Score::from_bytes(bytes);
+        }
+        if sum != nvram.read(2) {
+            return Self::new();
+        }
+        table.count = count;
+        table
+    }
+
+    pub fn save<N: Nvram>(&self, nvram: &mut N) {
+        let mut sum = MAGIC.wrapping_add(self.count as u8);
+        for (i, entry) in self.entries().iter().enumerate() {
+            for (j, byte) in entry.to_bytes().iter().enumerate() {
+                nvram.write(HEADER + i * ENTRY + j, *byte);
+                sum = sum.wrapping_add(*byte);
+            }
+        }
+        nvram.write(1, self.count as u8);
+        nvram.write(2, sum);
+        nvram.write(0, MAGIC);
+    }
+
+    pub fn entries(&self) -> &[Score] {
+        &self.entries[..self.count]
+    }
+
+    /// Returns true if a run with this many points would make it onto the table.
+    pub fn qualifies(&self, points: isize) -> bool {
+        points > 0 && (self.count < MAX_SCORES || points > self.entries[MAX_SCORES - 1].points)
+    }
+
+    /// Adds the run in order, pushing the lowest entry off a full table.
+    /// Returns its rank counting from 0, or None if it didn't make the table.
+    pub fn insert(&mut self, score: Score) -> Option<usize> {
+        if !self.qualifies(score.points) {
+            return None;
+        }
+        let rank = self.entries().iter().position(|e| score.points > e.points).unwrap_or(self.count);
+        if self.count < MAX_SCORES {
+            self.count += 1;
+        }
+        for i in (rank + 1..self.count).rev() {
+            self.entries[i] = self.entries[i - 1];
+        }
+        self.entries[rank] = score;
+        Some(rank)
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn draw<C: Canvas>(&self, canvas: &mut C, col: usize, row: usize) {
+        let heading = ColorCode::new(Color::Yellow, Color::Black);
+        let text = ColorCode::new(Color::White, Color::Black);
+        canvas.plot_str("    Name   Score   Seed", col, row, heading);
+        if self.count == 0 {
+            canvas.plot_str("No scores yet", col + 4, row + 2, text);
+        }
+        for (i, entry) in self.entries().iter().enumerate() {
+            let row = row + 2 + i;
+            let end = canvas.plot_num(i as isize + 1, col, row, text);
+            canvas.plot_str(".", end, row, text);
+            for (j, c) in entry.initials.iter().enumerate() {
+                canvas.plot(*c as char, col + 4 + j, row, text);
+            }
+            canvas.plot_num(entry.points, col + 11, row, text);
+            canvas.plot_num(entry.seed as isize, col + 19, row, text);
+        }
+    }
+}
+
+/// Arcade-style entry of three initials. Letters can be typed, or picked with the up and
+/// down arrows; left and right move between them. Only characters `plot` can show are taken.
+#[derive(Copy, Clone)]
+pub struct InitialsEntry {
+    initials: [u8; 3],
+    // Which initials the player has set, so backspace skips back over a blank one.
+    typed: [bool; 3],
+    // The initial that arrows change, and that the next typed letter replaces.
+    cursor: usize,
+}
+
+impl InitialsEntry {
+    pub fn new() -> Self {
+        Self {initials: *b"AAA", typed: [false; 3], cursor: 0}
+    }
+
+    pub fn initials(&self) -> [u8; 3] {
+        self.initials
+    }
+
+    /// Handles a key. Returns true once the initials are confirmed.
+    pub fn key(&mut self, key: DecodedKey) -> bool {
+        let last = self.initials.len() - 1;
+        match key {
+            DecodedKey::Unicode('\n') | DecodedKey::RawKey(KeyCode::Enter) => return true,
+            DecodedKey::Unicode('\x08') | DecodedKey::RawKey(KeyCode::Backspace) => {
+                // Clear the initial under the cursor, or the one before it if that is still blank.
+                if !self.typed[self.cursor] {
+                    self.cursor = self.cursor.saturating_sub(1);
+                }
+                self.initials[self.cursor] = ALPHABET[0];
+                self.typed[self.cursor] = false;
+            }
+            DecodedKey::Unicode(c) if c.is_ascii_graphic() => {
+                self.initials[self.cursor] = c.to_ascii_uppercase() as u8;
+                self.typed[self.cursor] = true;
+                self.cursor = (self.cursor + 1).min(last);
+            }
+            DecodedKey::RawKey(KeyCode::ArrowUp) => self.step(1),
+            DecodedKey::RawKey(KeyCode::ArrowDown) => self.step(ALPHABET.len() - 1),
+            DecodedKey::RawKey(KeyCode::ArrowLeft) => self.cursor = self.cursor.saturating_sub(1),
+            DecodedKey::RawKey(KeyCode::ArrowRight) => self.cursor = (self.cursor + 1).min(last),
+            _ => {}
+        }
+        false
+    }
+
+    // A character typed from outside the alphabet starts over at 'A'.
+    fn step(&mut self, by: usize) {
+        let i = self.cursor;
+        self.typed[i] = true;
+        self.initials[i] = match ALPHABET.iter().position(|c| *c == self.initials[i]) {
+            Some(p) => ALPHABET[(p + by) % ALPHABET.len()],
+            None => ALPHABET[0],
+        };
+    }
+
+    pub fn draw<C: Canvas>(&self, canvas: &mut C, col: usize, row: usize, color: ColorCode) {
+        for (i, c) in self.initials.iter().enumerate() {
+            let color = if i == self.cursor {ColorCode::new(Color::Black, Color::Yellow)} else {color};
+            canvas.plot(*c as char, col + i * 2, row, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nvram::RamNvram;
+
+    fn table() -> ScoreTable {
+        let mut table = ScoreTable::new();
+        table.insert(Score {initials: *b"ABC", points: 1200, seed: 7});
+        table.insert(Score {initials: *b"XYZ", points: 0xFF_FFFF + 5, seed: u32::MAX});
+        table.insert(Score {initials: *b"Q9Q", points: 40, seed: 123_456});
+        table
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        let mut nvram = RamNvram::new();
+        assert_eq!(ScoreTable::load(&mut nvram).entries(), &[]);
+        table().save(&mut nvram);
+        let loaded = ScoreTable::load(&mut nvram);
+        let mut expected = table();
+        expected.entries[0].points = MAX_POINTS;
+        assert_eq!(loaded.entries(), expected.entries());
+    }
+
+    #[test]
+    fn damaged_tables_load_empty() {
+        for addr in [2, HEADER + 4] {
+            let mut nvram = RamNvram::new();
+            table().save(&mut nvram);
+            let byte = nvram.read(addr);
+            nvram.write(addr, byte ^ 0x10);
+            assert_eq!(ScoreTable::load(&mut nvram).entries(), &[], "{}", addr);
+        }
+    }
+
+    #[test]
+    fn a_damaged_count_loads_empty() {
+        // The slot after the last entry was never written, so its bytes add nothing to a sum.
+        for count in [2, 4] {
+            let mut nvram = RamNvram::new();
+            table().save(&mut nvram);
+            nvram.write(1, count);
+            assert_eq!(ScoreTable::load(&mut nvram).entries(), &[], "{}", count);
+        }
+    }
+
+    fn enter(entry: &mut InitialsEntry, keys: &[DecodedKey]) -> [u8; 3] {
+        for key in keys {
+            assert!(!entry.key(*key));
+        }
+        entry.initials()
+    }
+
+    #[test]
+    fn typing_fills_initials_in_order() {
+        let mut entry = InitialsEntry::new();
+        let keys = [DecodedKey::Unicode('j'), DecodedKey::Unicode('D'), DecodedKey::Unicode('k')];
+        assert_eq!(&enter(&mut entry, &keys), b"JDK");
+        // The last initial is replaced rather than typing past the end.
+        assert_eq!(&enter(&mut entry, &[DecodedKey::Unicode('x')]), b"JDX");
+        assert!(entry.key(DecodedKey::RawKey(KeyCode::Enter)));
+    }
+
+    #[test]
+    fn arrows_move_the_cursor_and_cycle_letters() {
+        let mut entry = InitialsEntry::new();
+        let up = DecodedKey::RawKey(KeyCode::ArrowUp);
+        let down = DecodedKey::RawKey(KeyCode::ArrowDown);
+        let right = DecodedKey::RawKey(KeyCode::ArrowRight);
+        let left = DecodedKey::RawKey(KeyCode::ArrowLeft);
+        assert_eq!(&enter(&mut entry, &[down]), b"9AA");
+        assert_eq!(&enter(&mut entry, &[up, up, right, up]), b"BBA");
+        assert_eq!(&enter(&mut entry, &[right, right, right, up]), b"BBB");
+        assert_eq!(&enter(&mut entry, &[left, left, left, DecodedKey::Unicode('z')]), b"ZBB");
+        assert_eq!(entry.cursor, 1);
+    }
+
+    #[test]
+    fn backspace_clears_the_initial_at_the_cursor() {
+        let mut entry = InitialsEntry::new();
+        let back = DecodedKey::RawKey(KeyCode::Backspace);
+        let left = DecodedKey::RawKey(KeyCode::ArrowLeft);
+        // The cursor waits on the blank third initial, so backspace takes the one before.
+        assert_eq!(&enter(&mut entry, &[DecodedKey::Unicode('Q'), DecodedKey::Unicode('R'), back]), b"QAA");
+        assert_eq!(entry.cursor, 1);
+        assert_eq!(&enter(&mut entry, &[DecodedKey::Unicode('R'), DecodedKey::Unicode('S')]), b"QRS");
+        // After moving back, it clears the initial under the cursor and not the last one typed.
+        assert_eq!(&enter(&mut entry, &[left, back]), b"QAS");
+        assert_eq!(entry.cursor, 1);
+        assert_eq!(&enter(&mut entry, &[back, back, back]), b"AAS");
+        assert_eq!(entry.cursor, 0);
+    }
+
+    #[test]
+    fn non_printable_keys_are_ignored() {
+        let mut entry = InitialsEntry::new();
+        let keys = [
+            DecodedKey::Unicode(' '), DecodedKey::Unicode('\t'), DecodedKey::Unicode('\x1b'),
+            DecodedKey::Unicode('é'), DecodedKey::RawKey(KeyCode::F1),
+        ];
+        assert_eq!(&enter(&mut entry, &keys), b"AAA");
+        assert_eq!(entry.cursor, 0);
+    }
+}
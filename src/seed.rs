@@ -0,0 +1,120 @@
+use crate::platform::{ColorCode, Color};
+use pc_keyboard::{DecodedKey, KeyCode};
+use crate::canvas::Canvas;
+use crate::nvram::Cmos;
+
+// The real-time clock's seconds, minutes, hours, day, month and year registers.
+const CLOCK_REGISTERS: [u8; 6] = [0x00, 0x02, 0x04, 0x07, 0x08, 0x09];
+
+// u32::MAX has ten digits.
+const MAX_DIGITS: usize = 10;
+
+/// A seed that differs from boot to boot: the wall-clock time from the CMOS clock, mixed with
+/// the CPU's cycle counter so two boots in the same second still differ.
+pub fn hardware_seed() -> u32 {
+    let clock = CLOCK_REGISTERS.iter()
+        .fold(0u32, |acc, register| acc.wrapping_mul(61).wrapping_add(Cmos::read_register(*register) as u32));
+    let tsc = unsafe { core::arch::x86_64::_rdtsc() };
+    mix(clock ^ tsc as u32 ^ (tsc >> 32) as u32)
+}
+
+/// Scrambles a value so that nearby inputs give unrelated seeds. Used to roll the next seed
+/// from the last one.
+pub fn mix(value: u32) -> u32 {
+    let mut x = value;
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7FEB_352D);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846C_A68B);
+    x ^ (x >> 16)
+}
+
+/// Typing in a seed to replay a known run.
+#[derive(Copy, Clone)]
+pub struct SeedEntry {
+    digits: [u8; MAX_DIGITS],
+    len: usize,
+}
+
+impl SeedEntry {
+    pub fn new() -> Self {
+        Self {digits: [0; MAX_DIGITS], len: 0}
+    }
+
+    /// The seed typed so far, or None if nothing has been typed or it is too big for a seed.
+    pub fn value(&self) -> Option<u32> {
+        if self.len == 0 {
+            return None;
+        }
+        self.digits[..self.len].iter()
+            .try_fold(0u32, |acc, d| acc.checked_mul(10).and_then(|acc| acc.checked_add(*d as u32)))
+    }
+
+    /// Handles a key. Returns true when Enter is pressed on a usable seed.
+    pub fn key(&mut self, key: DecodedKey) -> bool {
+        match key {
+            DecodedKey::Unicode('\n') | DecodedKey::RawKey(KeyCode::Enter) => return self.value().is_some(),
+            DecodedKey::Unicode('\x08') | DecodedKey::RawKey(KeyCode::Backspace) => {
+                self.len = self.len.saturating_sub(1);
+            }
+            DecodedKey::Unicode(c @ '0'..='9') if self.len < MAX_DIGITS => {
+                self.digits[self.len] = c as u8 - b'0';
+                self.len += 1;
+            }
+            _ => {}
+        }
+        false
+    }
+
+    pub fn draw<C: Canvas>(&self, canvas: &mut C, col: usize, row: usize, color: ColorCode) {
+        for (i, d) in self.digits[..self.len].iter().enumerate() {
+            canvas.plot((b'0' + d) as char, col + i, row, color);
+        }
+        let cursor = if self.value().is_some() || self.len == 0 {Color::Yellow} else {Color::Red};
+        canvas.plot(' ', col + self.len, row, ColorCode::new(Color::Black, cursor));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(keys: &str) -> SeedEntry {
+        let mut entry = SeedEntry::new();
+        for c in keys.chars() {
+            assert!(!entry.key(DecodedKey::Unicode(c)));
+        }
+        entry
+    }
+
+    #[test]
+    fn reads_the_digits_typed() {
+        assert_eq!(SeedEntry::new().value(), None);
+        assert_eq!(typed("0").value(), Some(0));
+        assert_eq!(typed("04093").value(), Some(4093));
+        assert_eq!(typed("4294967295").value(), Some(u32::MAX));
+        assert_eq!(typed("4294967296").value(), None);
+        // Anything but a digit is ignored, as is an eleventh digit.
+        assert_eq!(typed("1a2 -3").value(), Some(123));
+        assert_eq!(typed("12345678901").value(), Some(1_234_567_890));
+    }
+
+    #[test]
+    fn backspace_drops_the_last_digit() {
+        let mut entry = typed("987");
+        entry.key(DecodedKey::RawKey(KeyCode::Backspace));
+        assert_eq!(entry.value(), Some(98));
+        entry.key(DecodedKey::Unicode('\x08'));
+        entry.key(DecodedKey::Unicode('\x08'));
+        entry.key(DecodedKey::Unicode('\x08'));
+        assert_eq!(entry.value(), None);
+        assert_eq!(typed("5").value(), Some(5));
+    }
+
+    #[test]
+    fn enter_needs_a_usable_seed() {
+        assert!(!SeedEntry::new().key(DecodedKey::Unicode('\n')));
+        assert!(!typed("9999999999").key(DecodedKey::RawKey(KeyCode::Enter)));
+        assert!(typed("42").key(DecodedKey::RawKey(KeyCode::Enter)));
+    }
+}